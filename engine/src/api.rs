@@ -0,0 +1,336 @@
+//! An engine that can be driven directly from Rust, without going through the
+//! UGMI protocol.
+//!
+//! ```
+//! use kinko::{Engine, Limits};
+//!
+//! let mut engine = Engine::new();
+//!
+//! engine.set_position(&["j10".parse().unwrap(), "k11".parse().unwrap()]).unwrap();
+//! engine.on_progress(|progress| println!("depth {} score {}", progress.depth, progress.score));
+//!
+//! let result = engine.search(&Limits::depth(4)).unwrap();
+//!
+//! println!("best move {} after {} nodes", result.bestmove, result.nodes);
+//! ```
+
+use std::io;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+use crate::core::{
+    bitboard::BitboardIter,
+    board::Board,
+    types::{Square, Stone},
+};
+use crate::engine::{
+    eval,
+    score::Score,
+    search::{self, Params, SearchData},
+    tt::{Bound, TranspositionTable},
+};
+
+/// The limits of a search. The search stops as soon as one of them is
+/// reached, and only on [`StopHandle::stop`] if none is set.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The maximum depth of the iterative deepening, in plies.
+    pub depth: Option<u16>,
+    /// The maximum number of nodes to search.
+    pub nodes: Option<u64>,
+    /// The maximum time to search.
+    pub movetime: Option<Duration>,
+    /// The remaining time on the clock of the side to move, of which the
+    /// engine spends a share, as in a game.
+    pub time: Option<Duration>,
+    /// The increment of the side to move, used with `time`.
+    pub inc: Option<Duration>,
+}
+
+impl Limits {
+    /// Limits the search to the given depth only.
+    pub fn depth(depth: u16) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Self::default()
+        }
+    }
+
+    /// Limits the search to the given number of nodes only.
+    pub fn nodes(nodes: u64) -> Self {
+        Self {
+            nodes: Some(nodes),
+            ..Self::default()
+        }
+    }
+
+    /// Limits the search to the given time only.
+    pub fn movetime(movetime: Duration) -> Self {
+        Self {
+            movetime: Some(movetime),
+            ..Self::default()
+        }
+    }
+
+    fn params(&self, turn: Stone) -> Params {
+        let mut params = Params::new();
+
+        if let Some(depth) = self.depth {
+            params.set_depth(depth);
+        }
+        if let Some(nodes) = self.nodes {
+            params.set_nodes(nodes);
+        }
+        if let Some(movetime) = self.movetime {
+            params.set_movetime(movetime);
+        }
+        if let Some(time) = self.time {
+            let inc = self.inc.unwrap_or_default();
+
+            params.set_btime(time);
+            params.set_wtime(time);
+            params.set_binc(inc);
+            params.set_winc(inc);
+            params.allocate_time(turn);
+        }
+
+        params
+    }
+}
+
+/// The state of a search after an iteration, or after a search window
+/// failure when `bound` is not [`Bound::Exact`].
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// The depth of the iteration.
+    pub depth: u16,
+    /// The deepest ply reached in the iteration.
+    pub seldepth: u16,
+    /// The score, from the point of view of the side to move.
+    pub score: Score,
+    /// Whether the score is exact or only a bound of the real score.
+    pub bound: Bound,
+    /// The best line found, starting with the best move.
+    pub pv: Vec<Square>,
+    /// The number of nodes searched so far.
+    pub nodes: u64,
+    /// The time spent so far.
+    pub time: Duration,
+}
+
+/// The outcome of a search.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// The move to play.
+    pub bestmove: Square,
+    /// The score of the last completed iteration, from the point of view of
+    /// the side to move.
+    pub score: Score,
+    /// The best line found, starting with the best move.
+    pub pv: Vec<Square>,
+    /// The depth of the last completed iteration.
+    pub depth: u16,
+    /// The deepest ply reached in the search.
+    pub seldepth: u16,
+    /// The number of nodes searched.
+    pub nodes: u64,
+    /// The time spent searching.
+    pub time: Duration,
+}
+
+/// Stops the search of an [`Engine`] from another thread.
+#[derive(Clone, Debug)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    /// Asks the running search to stop as soon as possible. The search then
+    /// returns the result of its last completed iteration. Calls made while
+    /// no search is running have no effect.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+type ProgressCallback = Box<dyn FnMut(&Progress) + Send>;
+
+/// A single-threaded engine with its own position and transposition table.
+///
+/// Several engines can live in the same program, but they share the options
+/// of the [`OPTIONS`](crate::engine::options::OPTIONS) registry, such as the
+/// evaluation weights and the pruning parameters.
+pub struct Engine {
+    board: Board,
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    progress: Option<ProgressCallback>,
+}
+
+impl Engine {
+    /// Creates an engine on the empty board, with a transposition table of
+    /// the default size.
+    pub fn new() -> Self {
+        Self::with_hash(TranspositionTable::DEFAULT_MB)
+    }
+
+    /// Creates an engine on the empty board, with a transposition table of
+    /// the given size in megabytes.
+    pub fn with_hash(megabytes: usize) -> Self {
+        Self {
+            board: Board::new(),
+            tt: Arc::new(TranspositionTable::new(megabytes)),
+            stop: Arc::new(AtomicBool::new(false)),
+            progress: None,
+        }
+    }
+
+    /// Sets the position to the empty board followed by the given moves,
+    /// black playing first. The position is left unchanged if a move is
+    /// outside the board or on an occupied square.
+    pub fn set_position(&mut self, moves: &[Square]) -> io::Result<()> {
+        let mut board = Board::new();
+
+        for &mv in moves {
+            if !mv.is_valid() || board.stone_at(mv) != Stone::Empty {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("illegal move '{}'", mv),
+                ));
+            }
+
+            board.push(mv);
+        }
+
+        self.board = board;
+        Ok(())
+    }
+
+    /// Sets the position to the given board.
+    pub fn set_board(&mut self, board: Board) {
+        self.board = board;
+    }
+
+    /// Returns the current position.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Forgets what previous searches learnt, before analysing positions from
+    /// another game.
+    pub fn new_game(&self) {
+        self.tt.clear();
+    }
+
+    /// Registers a function called with the progress of every search, after
+    /// each iteration. It replaces any previously registered function.
+    pub fn on_progress<F>(&mut self, callback: F)
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+    }
+
+    /// Returns a handle stopping the searches of this engine, which can be
+    /// sent to another thread.
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(self.stop.clone())
+    }
+
+    /// Searches the current position within the limits, blocking until the
+    /// search is over. Searching a position where the game is over fails.
+    ///
+    /// The best move is always legal: when the search stops before completing
+    /// its first iteration, it is the immediate tactic, the move of the
+    /// transposition table or else the first legal move, with a depth of 0.
+    pub fn search(&mut self, limits: &Limits) -> io::Result<SearchResult> {
+        if self.board.bitboard(Stone::Empty).is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the board is full",
+            ));
+        }
+
+        if is_game_over(&self.board) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the game is over",
+            ));
+        }
+
+        let params = limits.params(self.board.turn());
+        let mut data = SearchData::with_stop(self.stop.clone());
+        let board = &self.board;
+        let tt = &self.tt;
+        let progress = &mut self.progress;
+
+        self.stop.store(false, Ordering::Relaxed);
+        tt.new_search();
+        data.set_tt(tt.clone());
+
+        let score = search::iterative_search(board, &params, &mut data, |data, score, bound| {
+            if let Some(callback) = progress.as_mut() {
+                callback(&Progress {
+                    depth: data.iter_depth(),
+                    seldepth: data.seldepth(),
+                    score,
+                    bound,
+                    pv: principal_variation(board, tt, data.bestmove()),
+                    nodes: data.node_count(),
+                    time: data.elapsed(),
+                });
+            }
+
+            Ok(())
+        })?;
+
+        Ok(SearchResult {
+            bestmove: data.bestmove(),
+            score,
+            pv: principal_variation(board, tt, data.bestmove()),
+            depth: data.completed_depth(),
+            seldepth: data.seldepth(),
+            nodes: data.node_count(),
+            time: data.elapsed(),
+        })
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Whether a five is on the board, for either side.
+fn is_game_over(board: &Board) -> bool {
+    let stones = board.bitboard(Stone::Black) | board.bitboard(Stone::White);
+
+    BitboardIter::from_bitboard(&stones).any(|sq| eval::is_victory(board, sq))
+}
+
+// Follows the best moves stored in the transposition table from the best move,
+// until the game ends or the table has no legal move for the position.
+fn principal_variation(board: &Board, tt: &TranspositionTable, bestmove: Square) -> Vec<Square> {
+    let mut board = board.clone();
+    let mut pv = Vec::new();
+    let mut next = Some(bestmove);
+
+    while let Some(mv) = next {
+        if !mv.is_valid() || board.stone_at(mv) != Stone::Empty || pv.len() >= 64 {
+            break;
+        }
+
+        board.push(mv);
+        pv.push(mv);
+
+        if eval::is_victory(&board, mv) {
+            break;
+        }
+
+        next = tt.probe(board.key()).and_then(|entry| entry.bestmove());
+    }
+
+    pv
+}
@@ -0,0 +1,5 @@
+use kinko::tools::engine_match::{self, MatchParams};
+
+fn main() -> std::io::Result<()> {
+    engine_match::run(&MatchParams::from_args(std::env::args().skip(1))?)
+}
@@ -4,7 +4,7 @@ use std::ops::{
     ShrAssign,
 };
 
-use super::types::{File, Rank, Square, ROW_SIZE};
+use super::types::{File, Rank, Square, Symmetry, ROW_SIZE};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Bitboard([u64; 6]);
@@ -37,6 +37,16 @@ impl Bitboard {
         0x000001FFFFC00000,
     ]);
 
+    // The squares of the board, the padding bits of the last word excluded.
+    pub const FULL: Self = Self([
+        0xFFFFFFFFFFFFFFFF,
+        0xFFFFFFFFFFFFFFFF,
+        0xFFFFFFFFFFFFFFFF,
+        0xFFFFFFFFFFFFFFFF,
+        0xFFFFFFFFFFFFFFFF,
+        0x000001FFFFFFFFFF,
+    ]);
+
     pub fn new() -> Self {
         Self([0; 6])
     }
@@ -84,6 +94,16 @@ impl Bitboard {
     pub fn shift_right(&self) -> Self {
         (*self & !Self::RIGHT_EDGE) << 1
     }
+
+    pub fn transform(&self, sym: Symmetry) -> Self {
+        let mut bitboard = Self::new();
+
+        for sq in BitboardIter::from_bitboard(self) {
+            bitboard.set_square(sq.transform(sym));
+        }
+
+        bitboard
+    }
 }
 
 impl Default for Bitboard {
@@ -188,7 +208,7 @@ impl Not for Bitboard {
         let mut result = self;
 
         result.0.iter_mut().for_each(|v| *v = !*v);
-        result
+        result & Self::FULL
     }
 }
 
@@ -1,12 +1,39 @@
 use super::bitboard::{Bitboard, BitboardIter};
-use super::types::{File, Rank, Square, Stone, BOARD_SIZE};
+use super::types::{File, Rank, Square, Stone, Symmetry, BOARD_SIZE, ROW_SIZE};
 use std::fmt;
+use std::io;
+
+// Keys are stored in book files, so they come from a generator defined here
+// rather than from a library whose streams may change between versions.
+lazy_static! {
+    static ref ZOBRIST: [[u64; BOARD_SIZE]; 2] = {
+        let mut state = 0x6B696E6B6F;
+        let mut keys = [[0; BOARD_SIZE]; 2];
+
+        keys.iter_mut()
+            .flatten()
+            .for_each(|key| *key = splitmix64(&mut state));
+        keys
+    };
+    static ref ZOBRIST_TURN: u64 = splitmix64(&mut 0x7475726E);
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+
+    let mut z = *state;
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
 
 #[derive(Clone, Debug)]
 pub struct Board {
     bitsets: [Bitboard; 2],
     table: [Stone; BOARD_SIZE],
     turn: Stone,
+    key: u64,
 }
 
 impl Default for Board {
@@ -31,40 +58,121 @@ impl Board {
             bitsets: [Bitboard::new(); 2],
             table: [Stone::Empty; BOARD_SIZE],
             turn: Stone::Black,
+            key: 0,
         }
     }
 
-    pub fn setup_position(&mut self, grid: &str, turn: &str) {
-        self.reset();
+    // Sets up the position from a board string, listing the ranks separated by
+    // slashes with `x` for black stones, `o` for white stones and counts of
+    // empty squares, and from the side to move (`b` or `w`). The board is left
+    // untouched if either is ill-formed.
+    pub fn setup_position(&mut self, grid: &str, turn: &str) -> io::Result<()> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid position '{} {}'", grid, turn),
+            )
+        };
+        let turn = match turn {
+            "b" => Stone::Black,
+            "w" => Stone::White,
+            _ => return Err(invalid()),
+        };
+        let ranks = grid.split('/').collect::<Vec<_>>();
+        let mut stones = Vec::new();
 
-        let ranks = grid.split('/');
+        if ranks.len() != ROW_SIZE as usize {
+            return Err(invalid());
+        }
 
-        for (rank_idx, rank_str) in ranks.enumerate() {
+        for (rank_idx, rank_str) in ranks.iter().enumerate() {
             let mut empty_span = 0;
-            let rank = Rank::new(rank_idx as u8);
-            let mut file = File::new(0);
+            let mut file = 0;
 
-            for b in rank_str.chars() {
-                if let Some(value) = b.to_digit(10) {
+            for c in rank_str.chars() {
+                if let Some(value) = c.to_digit(10) {
                     empty_span = empty_span * 10 + value;
-                } else {
-                    file += empty_span as u8;
-                    empty_span = 0;
-                    if b == 'x' {
-                        self.add_stone(Square::from(file, rank), Stone::Black);
-                    } else if b == 'o' {
-                        self.add_stone(Square::from(file, rank), Stone::White);
+
+                    if empty_span > ROW_SIZE as u32 {
+                        return Err(invalid());
                     }
-                    file += 1;
+
+                    continue;
+                }
+
+                file += empty_span;
+                empty_span = 0;
+
+                if file >= ROW_SIZE as u32 {
+                    return Err(invalid());
+                }
+
+                let sq = Square::from(File::new(file as u8), Rank::new(rank_idx as u8));
+
+                match c {
+                    'x' => stones.push((sq, Stone::Black)),
+                    'o' => stones.push((sq, Stone::White)),
+                    _ => return Err(invalid()),
                 }
+
+                file += 1;
+            }
+
+            if file + empty_span != ROW_SIZE as u32 {
+                return Err(invalid());
             }
         }
 
-        match turn {
-            "w" => Stone::White,
-            "b" => Stone::Black,
-            _ => Stone::Black,
-        };
+        self.reset();
+
+        for (sq, stone) in stones {
+            self.add_stone(sq, stone);
+        }
+
+        self.turn = turn;
+        Ok(())
+    }
+
+    pub fn boardstring(&self) -> String {
+        let mut ranks = Vec::with_capacity(ROW_SIZE as usize);
+        let mut rank = Rank::FIRST;
+
+        while rank <= Rank::LAST {
+            let mut rank_str = String::new();
+            let mut empty_span = 0;
+            let mut file = File::FIRST;
+
+            while file <= File::LAST {
+                match self.stone_at(Square::from(file, rank)) {
+                    Stone::Empty => empty_span += 1,
+                    stone => {
+                        if empty_span != 0 {
+                            rank_str += &empty_span.to_string();
+                            empty_span = 0;
+                        }
+                        rank_str += &stone.to_string();
+                    }
+                }
+
+                file += 1;
+            }
+
+            if empty_span != 0 {
+                rank_str += &empty_span.to_string();
+            }
+
+            ranks.push(rank_str);
+            rank += 1;
+        }
+
+        ranks.join("/")
+    }
+
+    pub fn turnstring(&self) -> &'static str {
+        match self.turn {
+            Stone::White => "w",
+            _ => "b",
+        }
     }
 
     pub fn from_raw_parts(black: Bitboard, white: Bitboard, turn: Stone) -> Self {
@@ -72,19 +180,40 @@ impl Board {
             bitsets: [black, white],
             table: [Stone::Empty; BOARD_SIZE],
             turn,
+            key: 0,
         };
 
         for square in BitboardIter::from_bitboard(&black) {
             board.table[square.value() as usize] = Stone::Black;
+            board.key ^= ZOBRIST[0][square.value() as usize];
         }
 
         for square in BitboardIter::from_bitboard(&white) {
             board.table[square.value() as usize] = Stone::White;
+            board.key ^= ZOBRIST[1][square.value() as usize];
         }
 
         board
     }
 
+    pub fn transform(&self, sym: Symmetry) -> Self {
+        Self::from_raw_parts(
+            self.bitsets[0].transform(sym),
+            self.bitsets[1].transform(sym),
+            self.turn,
+        )
+    }
+
+    // Returns the symmetric image of the board with the smallest key, and the
+    // symmetry mapping this board to it (the first one on ties).
+    pub fn canonical(&self) -> (Self, Symmetry) {
+        Symmetry::ALL
+            .iter()
+            .map(|&sym| (self.transform(sym), sym))
+            .min_by_key(|(board, sym)| (board.key(), *sym))
+            .unwrap()
+    }
+
     pub fn turn(&self) -> Stone {
         self.turn
     }
@@ -97,9 +226,20 @@ impl Board {
         }
     }
 
+    pub fn key(&self) -> u64 {
+        if self.turn == Stone::White {
+            self.key ^ *ZOBRIST_TURN
+        } else {
+            self.key
+        }
+    }
+
     fn add_stone(&mut self, sq: Square, stone: Stone) {
+        let side = if stone == Stone::Black { 0 } else { 1 };
+
         self.table[sq.value() as usize] = stone;
-        self.bitsets[if stone == Stone::Black { 0 } else { 1 }].set_square(sq);
+        self.bitsets[side].set_square(sq);
+        self.key ^= ZOBRIST[side][sq.value() as usize];
     }
 
     pub fn stone_at(&self, sq: Square) -> Stone {
@@ -110,17 +250,76 @@ impl Board {
         self.bitsets = [Bitboard::new(); 2];
         self.table = [Stone::Empty; BOARD_SIZE];
         self.turn = Stone::Black;
+        self.key = 0;
     }
 
     pub fn push(&mut self, sq: Square) {
-        self.table[sq.value() as usize] = self.turn;
-        self.bitsets[if self.turn == Stone::Black { 0 } else { 1 }].set_square(sq);
+        self.add_stone(sq, self.turn);
+        self.turn = self.turn.flip();
+    }
+
+    pub fn push_null(&mut self) {
+        self.turn = self.turn.flip();
+    }
+
+    pub fn pop_null(&mut self) {
         self.turn = self.turn.flip();
     }
 
     pub fn pop(&mut self, sq: Square) {
         self.turn = self.turn.flip();
-        self.bitsets[if self.turn == Stone::Black { 0 } else { 1 }].rst_square(sq);
+
+        let side = if self.turn == Stone::Black { 0 } else { 1 };
+
+        self.bitsets[side].rst_square(sq);
         self.table[sq.value() as usize] = Stone::Empty;
+        self.key ^= ZOBRIST[side][sq.value() as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // Book files store keys, which must not change between builds.
+    #[test]
+    fn keys_are_stable() {
+        let mut board = Board::new();
+
+        board.push("j10".parse().unwrap());
+        assert_eq!(board.key(), 0xA56E_25DE_D877_409B);
+        board.push("k11".parse().unwrap());
+        assert_eq!(board.key(), 0x9FC7_DC2D_7601_FCAF);
+    }
+
+    #[test]
+    fn canonical_is_shared_by_all_symmetric_images() {
+        let mut rng = StdRng::seed_from_u64(0x63616e6f6e);
+
+        for _ in 0..50 {
+            let mut board = Board::new();
+
+            for _ in 0..rng.gen_range(0..30) {
+                let sq = Square::new(rng.gen_range(0..BOARD_SIZE as u16));
+
+                if board.stone_at(sq) == Stone::Empty {
+                    board.push(sq);
+                }
+            }
+
+            let (canonical, sym) = board.canonical();
+
+            assert_eq!(board.transform(sym).key(), canonical.key());
+
+            for image in Symmetry::ALL.map(|sym| board.transform(sym)) {
+                let (image_canonical, image_sym) = image.canonical();
+
+                assert_eq!(image_canonical.key(), canonical.key());
+                assert_eq!(image_canonical.boardstring(), canonical.boardstring());
+                assert_eq!(image_canonical.turn(), board.turn());
+                assert_eq!(image.transform(image_sym).key(), canonical.key());
+            }
+        }
     }
 }
@@ -1,6 +1,5 @@
-use std::str::FromStr;
-
 use super::{
+    bitboard::{Bitboard, BitboardIter},
     board::Board,
     types::{Direction, Square, Stone},
 };
@@ -15,91 +14,200 @@ pub enum Alignment {
     Five,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Alignment {
+    pub const LIST: [Self; 6] = [
+        Self::NoAlign,
+        Self::Three,
+        Self::OpenThree,
+        Self::Four,
+        Self::OpenFour,
+        Self::Five,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct OwnedAlignment {
     align: Alignment,
     owned: bool,
+    gains: u8,
+    costs: u8,
 }
 
+// Each line is encoded as a 16-bit mask of the 8 squares surrounding the
+// centre stone, 2 bits per square: the 4 squares in the forward direction
+// occupy the low byte (nearest first), the 4 squares in the opposite
+// direction the high byte (nearest first). The gain and cost squares of an
+// alignment use the same order, one bit per square.
+const CELL_EMPTY: u16 = 0;
+const CELL_OWN: u16 = 1;
+const CELL_THEIRS: u16 = 2;
+const CELL_WALL: u16 = 3;
+
+const FIVE_WINDOWS: [u16; 5] = [0x1F, 0x3E, 0x7C, 0xF8, 0x1F0];
+const CENTER_BIT: u16 = 1 << 4;
+const BEFORE_CENTER: u16 = CENTER_BIT - 1;
+
+pub const LINES: [(Direction, Direction); 4] = [
+    (Direction::South, Direction::North),
+    (Direction::East, Direction::West),
+    (Direction::SouthEast, Direction::NorthWest),
+    (Direction::SouthWest, Direction::NorthEast),
+];
+
 impl OwnedAlignment {
     pub fn from(board: &Board, sq: Square, dir: Direction, opp_dir: Direction) -> Self {
-        let mut stone_buffer = [Stone::Empty; 9];
-        let mut s = sq;
+        MAGIC_STRUCT[line_mask(board, sq, board.stone_at(sq), dir, opp_dir) as usize]
+    }
 
-        for i in 0..=4 {
-            stone_buffer[4 + i] = board.stone_at(s);
+    pub fn after(
+        board: &Board,
+        sq: Square,
+        turn: Stone,
+        dir: Direction,
+        opp_dir: Direction,
+    ) -> Self {
+        MAGIC_STRUCT[line_mask(board, sq, turn, dir, opp_dir) as usize]
+    }
 
-            let next = s.shift(dir);
+    pub fn align(&self) -> Alignment {
+        self.align
+    }
 
-            if !next.is_valid() || s.distance(next) > 1 {
-                break;
-            }
+    pub fn owned(&self) -> bool {
+        self.owned
+    }
 
-            s = next;
-        }
+    // Empty squares where a stone of ours would upgrade the alignment, for
+    // example the squares turning a three into a four.
+    pub fn gain_squares(&self, sq: Square, dir: Direction, opp_dir: Direction) -> Bitboard {
+        neighbours_to_bitboard(self.gains, sq, dir, opp_dir)
+    }
+
+    // Empty squares where an opponent stone would downgrade the alignment,
+    // that is the squares defending against it.
+    pub fn cost_squares(&self, sq: Square, dir: Direction, opp_dir: Direction) -> Bitboard {
+        neighbours_to_bitboard(self.costs, sq, dir, opp_dir)
+    }
+}
 
-        s = sq;
+fn neighbours_to_bitboard(bits: u8, sq: Square, dir: Direction, opp_dir: Direction) -> Bitboard {
+    let mut bitboard = Bitboard::new();
+
+    for (side, d) in [dir, opp_dir].into_iter().enumerate() {
+        let mut s = sq;
 
         for i in 0..4 {
-            let next = s.shift(opp_dir);
+            s = s.shift(d);
 
-            if !next.is_valid() || s.distance(next) > 1 {
-                break;
+            if bits & (1 << (side * 4 + i)) != 0 {
+                bitboard.set_square(s);
             }
-
-            s = next;
-            stone_buffer[3 - i] = board.stone_at(s);
         }
+    }
+
+    bitboard
+}
 
-        MAGIC_STRUCT[stones_to_mask(&stone_buffer) as usize]
+pub fn threat_gains(board: &Board, sq: Square, min_align: Alignment) -> Bitboard {
+    let mut gains = Bitboard::new();
+
+    for (dir, opp_dir) in LINES {
+        let align = OwnedAlignment::from(board, sq, dir, opp_dir);
+
+        if align.align() >= min_align {
+            gains |= align.gain_squares(sq, dir, opp_dir);
+        }
     }
 
-    pub fn after(
-        board: &Board,
-        sq: Square,
-        turn: Stone,
-        dir: Direction,
-        opp_dir: Direction,
-    ) -> Self {
-        let mut stone_buffer = [Stone::Empty; 9];
-        let mut s = sq;
+    gains
+}
 
-        stone_buffer[4] = turn;
+// Empty squares where `side` would upgrade one of its alignments of at least
+// `min_align`, for example all squares completing a five for `Alignment::Four`.
+pub fn side_threat_gains(board: &Board, side: Stone, min_align: Alignment) -> Bitboard {
+    let mut gains = Bitboard::new();
 
-        for i in 1..=4 {
-            stone_buffer[4 + i] = board.stone_at(s);
+    for sq in BitboardIter::from_bitboard(&board.bitboard(side)) {
+        gains |= threat_gains(board, sq, min_align);
+    }
 
-            let next = s.shift(dir);
+    gains
+}
 
-            if !next.is_valid() || s.distance(next) > 1 {
-                break;
-            }
+// Empty squares where `side` would complete a five. The gains of a four also
+// contain the squares only turning it into an open four, which are filtered
+// out.
+pub fn side_fives(board: &Board, side: Stone) -> Bitboard {
+    let mut fives = side_threat_gains(board, side, Alignment::Four);
 
-            s = next;
+    for sq in BitboardIter::from_bitboard(&fives) {
+        if CrossAlignment::after(board, sq, side) != CrossAlignment::Five {
+            fives.rst_square(sq);
         }
+    }
 
-        s = sq;
+    fives
+}
 
-        for i in 0..4 {
-            let next = s.shift(opp_dir);
+// Empty squares where `side` would make at least a four on one of the lines.
+pub fn side_fours(board: &Board, side: Stone) -> Bitboard {
+    let mut fours = side_threat_gains(board, side, Alignment::Three);
 
-            if !next.is_valid() || s.distance(next) > 1 {
-                break;
-            }
+    for sq in BitboardIter::from_bitboard(&fours) {
+        let makes_four = LINES.iter().any(|&(dir, opp_dir)| {
+            OwnedAlignment::after(board, sq, side, dir, opp_dir).align() >= Alignment::Four
+        });
 
-            s = next;
-            stone_buffer[3 - i] = board.stone_at(s);
+        if !makes_four {
+            fours.rst_square(sq);
         }
+    }
+
+    fours
+}
+
+// Squares sharing a line with `sq` at a distance of at most 4, the squares a
+// stone on `sq` can form an alignment with.
+pub fn line_neighbourhood(sq: Square) -> Bitboard {
+    let mut bitboard = Bitboard::new();
+
+    for (dir, opp_dir) in LINES {
+        for d in [dir, opp_dir] {
+            let mut s = sq;
 
-        MAGIC_STRUCT[stones_to_mask(&stone_buffer) as usize]
+            for _ in 0..4 {
+                let file = s.file().value();
+
+                s = s.shift(d);
+
+                // Shifting past the left or right edge wraps to another rank.
+                if !s.is_valid() || s.file().value().abs_diff(file) > 1 {
+                    break;
+                }
+
+                bitboard.set_square(s);
+            }
+        }
     }
 
-    pub fn align(&self) -> Alignment {
-        self.align
+    bitboard
+}
+
+pub fn threat_defences(board: &Board, sq: Square, min_align: Alignment) -> Bitboard {
+    let mut costs = Bitboard::new();
+
+    for (dir, opp_dir) in LINES {
+        let align = OwnedAlignment::from(board, sq, dir, opp_dir);
+
+        if align.align() >= min_align {
+            costs |= align.cost_squares(sq, dir, opp_dir);
+        }
     }
+
+    costs
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CrossAlignment {
     NoAlign,
     Three,
@@ -115,6 +223,20 @@ pub enum CrossAlignment {
 }
 
 impl CrossAlignment {
+    pub const LIST: [Self; 11] = [
+        Self::NoAlign,
+        Self::Three,
+        Self::OpenThree,
+        Self::Four,
+        Self::OpenFour,
+        Self::DoubleThree,
+        Self::DoubleOpenThree,
+        Self::FourThree,
+        Self::OpenFourThree,
+        Self::FourFour,
+        Self::Five,
+    ];
+
     fn alignments_to_cross(alignments: &[Alignment; 4]) -> Self {
         match (alignments[3], alignments[2]) {
             (Alignment::Five, _) => Self::Five,
@@ -137,292 +259,370 @@ impl CrossAlignment {
     }
 
     pub fn from(board: &Board, sq: Square) -> Self {
-        let mut alignments = [
-            OwnedAlignment::from(board, sq, Direction::South, Direction::North).align(),
-            OwnedAlignment::from(board, sq, Direction::East, Direction::West).align(),
-            OwnedAlignment::from(board, sq, Direction::SouthEast, Direction::NorthWest).align(),
-            OwnedAlignment::from(board, sq, Direction::SouthWest, Direction::NorthEast).align(),
-        ];
+        Self::from_lines(
+            LINES.map(|(dir, opp_dir)| OwnedAlignment::from(board, sq, dir, opp_dir).align()),
+        )
+    }
 
+    pub fn from_lines(mut alignments: [Alignment; 4]) -> Self {
         alignments.as_mut_slice().sort_unstable();
         Self::alignments_to_cross(&alignments)
     }
 
     pub fn after(board: &Board, sq: Square, turn: Stone) -> Self {
-        let mut alignments = [
-            OwnedAlignment::after(board, sq, turn, Direction::South, Direction::North).align(),
-            OwnedAlignment::after(board, sq, turn, Direction::East, Direction::West).align(),
-            OwnedAlignment::after(board, sq, turn, Direction::SouthEast, Direction::NorthWest)
-                .align(),
-            OwnedAlignment::after(board, sq, turn, Direction::SouthWest, Direction::NorthEast)
-                .align(),
-        ];
-
-        alignments.as_mut_slice().sort_unstable();
-        Self::alignments_to_cross(&alignments)
+        Self::from_lines(
+            LINES
+                .map(|(dir, opp_dir)| OwnedAlignment::after(board, sq, turn, dir, opp_dir).align()),
+        )
     }
 }
 
-#[derive(Clone, Copy)]
-pub struct SquareAlignment {
-    cross_align: CrossAlignment,
-    owned: bool,
+lazy_static! {
+    pub static ref MAGIC_STRUCT: Box<[OwnedAlignment]> = build_magic();
 }
 
-struct StonePattern(Vec<Stone>);
+pub fn build_magic() -> Box<[OwnedAlignment]> {
+    (0..=u16::MAX).map(classify_mask).collect()
+}
+
+fn classify_mask(mask: u16) -> OwnedAlignment {
+    let (own, free) = mask_to_bits(mask);
+    let align = line_alignment(own, free);
+    let span = match align {
+        Alignment::NoAlign => 0,
+        Alignment::Three | Alignment::OpenThree => window_span(own, free, 3),
+        Alignment::Four | Alignment::OpenFour => window_span(own, free, 4),
+        Alignment::Five => window_span(own, free, 5),
+    };
+    let mut gains = 0;
+    let mut costs = 0;
+
+    for i in 0..9 {
+        let bit = 1 << i;
+
+        if free & bit == 0 {
+            continue;
+        }
+
+        if line_alignment(own | bit, free & !bit) > align {
+            gains |= bit;
+        }
+
+        if line_alignment(own, free & !bit) < align {
+            costs |= bit;
+        }
+    }
 
-impl StonePattern {
-    fn into_inner(self) -> Vec<Stone> {
-        self.0
+    OwnedAlignment {
+        align,
+        owned: align != Alignment::NoAlign && own & span & BEFORE_CENTER == 0,
+        gains: bits_to_neighbours(gains),
+        costs: bits_to_neighbours(costs),
     }
 }
 
-#[derive(Debug)]
-pub struct PatternError(());
-
-impl FromStr for StonePattern {
-    type Err = PatternError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(StonePattern(
-            s.as_bytes()
-                .iter()
-                .map(|b| match b {
-                    46 => Ok(Stone::Empty),
-                    120 => Ok(Stone::Black),
-                    111 => Ok(Stone::White),
-                    _ => Err(PatternError(())),
-                })
-                .collect::<Result<Vec<_>, PatternError>>()?,
-        ))
+// Splits a line mask into 9-bit sets of own and empty squares, bit 0 being
+// the farthest square in the opposite direction and bit 4 the centre.
+fn mask_to_bits(mask: u16) -> (u16, u16) {
+    let mut own = CENTER_BIT;
+    let mut free = 0;
+
+    for i in 0..8 {
+        match (mask >> (i * 2)) & 3 {
+            CELL_EMPTY => free |= neighbour_bit(i),
+            CELL_OWN => own |= neighbour_bit(i),
+            _ => (),
+        }
     }
+
+    (own, free)
 }
 
-lazy_static! {
-    pub static ref MAGIC_STRUCT: [OwnedAlignment; 0x10000] =
-        build_magic().expect("Failed to generate magic struct");
+fn neighbour_bit(index: u16) -> u16 {
+    if index < 4 {
+        1 << (5 + index)
+    } else {
+        1 << (7 - index)
+    }
 }
 
-pub fn build_magic() -> Result<[OwnedAlignment; 0x10000], PatternError> {
-    let mut magic = [OwnedAlignment {
-        align: Alignment::NoAlign,
-        owned: false,
-    }; 0x10000];
-
-    let mut _count = 0;
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &"xxxxx".parse::<StonePattern>()?.into_inner(),
-        Alignment::Five,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &".xxxx.".parse::<StonePattern>()?.into_inner(),
-        Alignment::OpenFour,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &"oxxxx.".parse::<StonePattern>()?.into_inner(),
-        Alignment::Four,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &".xxxxo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Four,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &"oxxxxo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Four,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &".xxx.".parse::<StonePattern>()?.into_inner(),
-        Alignment::OpenThree,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &"oxxx.".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &".xxxo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &"oxxxo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &".x.xx.".parse::<StonePattern>()?.into_inner(),
-        Alignment::OpenThree,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &"ox.xx.".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &".x.xxo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &"ox.xxo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &".xx.x.".parse::<StonePattern>()?.into_inner(),
-        Alignment::OpenThree,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &"oxx.x.".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &".xx.xo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &"oxx.xo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Three,
-    );
-
-    _count += magic_init_pattern(
-        &mut magic,
-        &".xx.xx.".parse::<StonePattern>()?.into_inner(),
-        Alignment::Four,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &"oxx.xx.".parse::<StonePattern>()?.into_inner(),
-        Alignment::Four,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &".xx.xxo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Four,
-    );
-    _count += magic_init_pattern(
-        &mut magic,
-        &"oxx.xxo".parse::<StonePattern>()?.into_inner(),
-        Alignment::Four,
-    );
-
-    // println!("Initialized {}/65536 patterns", _count);
-
-    Ok(magic)
+fn bits_to_neighbours(bits: u16) -> u8 {
+    (0..8)
+        .filter(|&i| bits & neighbour_bit(i) != 0)
+        .fold(0, |acc, i| acc | (1 << i))
 }
 
-pub fn magic_init_pattern(
-    magic: &mut [OwnedAlignment; 0x10000],
-    pattern: &Vec<Stone>,
-    align: Alignment,
-) -> u16 {
-    let mut base = [Stone::Empty; 9];
-    let mut count = 0;
+fn line_alignment(own: u16, free: u16) -> Alignment {
+    if window_span(own, free, 5) != 0 {
+        return Alignment::Five;
+    }
 
-    base[9 - pattern.len()..].copy_from_slice(pattern.as_slice());
+    match completion_squares(own, free).count_ones() {
+        0 => (),
+        1 => return Alignment::Four,
+        _ => return Alignment::OpenFour,
+    }
 
-    let range = 3i32.pow(9 - pattern.len() as u32);
+    let mut align = Alignment::NoAlign;
 
-    for stone_combination in 0..range {
-        let mut stone_iterator = stone_combination;
+    for i in 0..9 {
+        let bit = 1 << i;
 
-        for value in base.iter_mut().take(9 - pattern.len()) {
-            *value = match stone_iterator % 3 {
-                0 => Stone::Empty,
-                1 => Stone::Black,
-                _ => Stone::White,
-            };
+        if free & bit == 0 {
+            continue;
+        }
 
-            stone_iterator /= 3;
+        match completion_squares(own | bit, free & !bit).count_ones() {
+            0 => (),
+            1 => align = align.max(Alignment::Three),
+            _ => return Alignment::OpenThree,
         }
+    }
 
-        let mut shift = base;
-        let mut rotation = 0;
+    align
+}
 
-        while shift[4] != Stone::Black {
-            let left = shift[0];
+// Returns the set of empty squares that would complete a five.
+fn completion_squares(own: u16, free: u16) -> u16 {
+    FIVE_WINDOWS
+        .iter()
+        .filter(|&&w| w & !(own | free) == 0 && (w & free).count_ones() == 1)
+        .fold(0, |acc, w| acc | (w & free))
+}
+
+// Returns the union of the unblocked five-windows holding at least `stones`
+// own stones, that is the squares taking part in the alignment.
+fn window_span(own: u16, free: u16, stones: u32) -> u16 {
+    FIVE_WINDOWS
+        .iter()
+        .filter(|&&w| w & !(own | free) == 0 && (w & own).count_ones() >= stones)
+        .fold(0, |acc, w| acc | w)
+}
+
+fn line_mask(board: &Board, sq: Square, center: Stone, dir: Direction, opp_dir: Direction) -> u16 {
+    let cell = |s: Square| match board.stone_at(s) {
+        Stone::Empty => CELL_EMPTY,
+        stone if stone == center => CELL_OWN,
+        _ => CELL_THEIRS,
+    };
+
+    let mut mask = 0;
+
+    for (side, d) in [dir, opp_dir].into_iter().enumerate() {
+        let mut s = sq;
+
+        for i in 0..4 {
+            let next = s.shift(d);
+            let shift = (side * 4 + i) * 2;
 
-            for rot_idx in 0..8 {
-                shift[rot_idx] = shift[rot_idx + 1];
+            if !next.is_valid() || s.distance(next) > 1 {
+                for j in i..4 {
+                    mask |= CELL_WALL << ((side * 4 + j) * 2);
+                }
+                break;
             }
 
-            shift[8] = left;
-            rotation += 1;
+            s = next;
+            mask |= cell(s) << shift;
         }
+    }
 
-        magic[stones_to_mask(&shift) as usize] = OwnedAlignment { align, owned: true };
-        count += 1;
+    mask
+}
 
-        for _ in rotation..9 - pattern.len() {
-            let left = shift[0];
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            for rot_idx in 0..8 {
-                shift[rot_idx] = shift[rot_idx + 1];
-            }
+    #[derive(Clone, Copy, PartialEq)]
+    enum Cell {
+        Empty,
+        Own,
+        Blocked,
+    }
 
-            shift[8] = left;
+    fn decode(mask: u16) -> [Cell; 9] {
+        let mut cells = [Cell::Own; 9];
+        let code_to_cell = |code| match code {
+            0 => Cell::Empty,
+            1 => Cell::Own,
+            _ => Cell::Blocked,
+        };
 
-            if shift[4] == Stone::White {
-                break;
+        for i in 0..4 {
+            cells[5 + i] = code_to_cell((mask >> (i * 2)) & 3);
+            cells[3 - i] = code_to_cell((mask >> (8 + i * 2)) & 3);
+        }
+
+        cells
+    }
+
+    fn has_five(cells: &[Cell; 9]) -> bool {
+        cells.windows(5).any(|w| w.iter().all(|&c| c == Cell::Own))
+    }
+
+    fn completions(cells: &[Cell; 9]) -> Vec<usize> {
+        (0..9)
+            .filter(|&i| {
+                let mut next = *cells;
+
+                next[i] == Cell::Empty && {
+                    next[i] = Cell::Own;
+                    has_five(&next)
+                }
+            })
+            .collect()
+    }
+
+    fn reference_align(cells: &[Cell; 9]) -> Alignment {
+        if has_five(cells) {
+            return Alignment::Five;
+        }
+
+        match completions(cells).len() {
+            0 => (),
+            1 => return Alignment::Four,
+            _ => return Alignment::OpenFour,
+        }
+
+        let mut align = Alignment::NoAlign;
+
+        for i in 0..9 {
+            if cells[i] != Cell::Empty {
+                continue;
             }
 
-            if shift[4] == Stone::Empty {
+            let mut next = *cells;
+
+            next[i] = Cell::Own;
+            align = align.max(match completions(&next).len() {
+                0 => Alignment::NoAlign,
+                1 => Alignment::Three,
+                _ => Alignment::OpenThree,
+            });
+        }
+
+        align
+    }
+
+    fn reference_owned(cells: &[Cell; 9], align: Alignment) -> bool {
+        let stones = match align {
+            Alignment::NoAlign => return false,
+            Alignment::Three | Alignment::OpenThree => 3,
+            Alignment::Four | Alignment::OpenFour => 4,
+            Alignment::Five => 5,
+        };
+
+        (0..5)
+            .filter(|&start| {
+                let window = &cells[start..start + 5];
+
+                !window.contains(&Cell::Blocked)
+                    && window.iter().filter(|&&c| c == Cell::Own).count() >= stones
+            })
+            .all(|start| cells[start..4].iter().all(|&c| c != Cell::Own))
+    }
+
+    fn reference_squares(cells: &[Cell; 9], align: Alignment, stone: Cell) -> u8 {
+        let mut bits = 0;
+
+        for i in 0..8 {
+            let pos = if i < 4 { 5 + i } else { 7 - i };
+            let mut next = *cells;
+
+            if next[pos] != Cell::Empty {
                 continue;
             }
 
-            magic[stones_to_mask(&shift) as usize] = OwnedAlignment {
+            next[pos] = stone;
+
+            let next_align = reference_align(&next);
+
+            if (stone == Cell::Own && next_align > align)
+                || (stone == Cell::Blocked && next_align < align)
+            {
+                bits |= 1 << i;
+            }
+        }
+
+        bits
+    }
+
+    #[test]
+    fn magic_matches_reference() {
+        let magic = build_magic();
+
+        for mask in 0..=u16::MAX {
+            let cells = decode(mask);
+            let align = reference_align(&cells);
+            let expected = OwnedAlignment {
                 align,
-                owned: false,
+                owned: reference_owned(&cells, align),
+                gains: reference_squares(&cells, align, Cell::Own),
+                costs: reference_squares(&cells, align, Cell::Blocked),
             };
-            count += 1;
+
+            assert_eq!(magic[mask as usize], expected, "mask {:#06x}", mask);
         }
     }
 
-    count
-}
+    #[test]
+    fn broken_fours_at_edges() {
+        let mut board = Board::new();
 
-pub fn stones_to_mask(stones: &[Stone; 9]) -> u16 {
-    let main_stone = stones[4];
+        // x.xxx, xxx.x and xx.xx against the left edge of the first rank.
+        for moves in [
+            ["a01", "s19", "c01", "s18", "d01", "s17", "e01"],
+            ["a01", "s19", "b01", "s18", "c01", "s17", "e01"],
+            ["a01", "s19", "b01", "s18", "d01", "s17", "e01"],
+        ] {
+            board.reset();
 
-    let stone_to_mask = |v| {
-        if v == Stone::Empty {
-            0
-        } else if v == main_stone {
-            1
-        } else {
-            2
-        }
-    };
+            for mv in moves {
+                board.push(mv.parse().unwrap());
+            }
 
-    let mut mask = 0;
+            let align = OwnedAlignment::from(
+                &board,
+                "a01".parse().unwrap(),
+                Direction::East,
+                Direction::West,
+            );
 
-    for i in 0..4 {
-        mask |= stone_to_mask(stones[5 + i]) << (i * 2);
+            assert_eq!(align.align(), Alignment::Four);
+            assert!(align.owned());
+        }
     }
 
-    for i in 4..8 {
-        mask |= stone_to_mask(stones[7 - i]) << (i * 2);
+    #[test]
+    fn open_three_defences() {
+        let mut board = Board::new();
+
+        for mv in ["h08", "a01", "i08", "a03", "j08"] {
+            board.push(mv.parse().unwrap());
+        }
+
+        let sq = "h08".parse().unwrap();
+        let mut expected = Bitboard::new();
+
+        expected.set_square("g08".parse().unwrap());
+        expected.set_square("k08".parse().unwrap());
+
+        assert_eq!(
+            threat_defences(&board, sq, Alignment::OpenThree).raw_buffer(),
+            expected.raw_buffer()
+        );
+        assert_eq!(
+            threat_gains(&board, sq, Alignment::OpenThree).len(),
+            4,
+            "f08, g08, k08 and l08 all make a four"
+        );
     }
 
-    mask
+    #[test]
+    fn line_neighbourhood_stops_at_edges() {
+        assert_eq!(line_neighbourhood("j10".parse().unwrap()).len(), 32);
+        assert_eq!(line_neighbourhood("a01".parse().unwrap()).len(), 12);
+        assert_eq!(line_neighbourhood("s10".parse().unwrap()).len(), 20);
+    }
 }
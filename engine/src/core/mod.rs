@@ -1,4 +1,5 @@
 pub mod bitboard;
 pub mod board;
 pub mod magic;
+pub mod record;
 pub mod types;
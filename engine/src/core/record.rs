@@ -0,0 +1,568 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::board::Board;
+use super::magic::CrossAlignment;
+use super::types::{File, Rank, Square, Stone, ROW_SIZE};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameResult {
+    BlackWins,
+    WhiteWins,
+    Draw,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Psq,
+    RenLib,
+    Sgf,
+}
+
+impl Format {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match ext.as_str() {
+            "psq" => Some(Self::Psq),
+            "lib" => Some(Self::RenLib),
+            "sgf" => Some(Self::Sgf),
+            _ => None,
+        }
+    }
+}
+
+// A game as the sequence of its moves from the empty board, black first, with
+// the metadata the formats can carry.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Record {
+    pub moves: Vec<Square>,
+    pub black: Option<String>,
+    pub white: Option<String>,
+    pub result: Option<GameResult>,
+    pub rules: Option<String>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn square_at(x: usize, y: usize) -> io::Result<Square> {
+    if x >= ROW_SIZE as usize || y >= ROW_SIZE as usize {
+        return Err(invalid_data("move outside of the board"));
+    }
+
+    Ok(Square::from(File::new(x as u8), Rank::new(y as u8)))
+}
+
+// Places the coordinates along one axis of a board of `size` squares on ours:
+// smaller boards are centred like RenLib ones, and games on larger boards are
+// only accepted if they fit, shifted as little as needed.
+fn fit_axis(size: usize, coords: &mut [usize]) -> io::Result<()> {
+    let row_size = ROW_SIZE as usize;
+
+    if coords.iter().any(|&coord| coord >= size) {
+        return Err(invalid_data("move outside of the board"));
+    }
+
+    if size <= row_size {
+        coords
+            .iter_mut()
+            .for_each(|coord| *coord += (row_size - size) / 2);
+        return Ok(());
+    }
+
+    let shift = coords
+        .iter()
+        .max()
+        .map_or(0, |&max| max.saturating_sub(row_size - 1));
+
+    if coords.iter().any(|&coord| coord < shift) {
+        return Err(invalid_data("game too large for the board"));
+    }
+
+    coords.iter_mut().for_each(|coord| *coord -= shift);
+    Ok(())
+}
+
+// Moves as 0-based coordinates on the board of the record, the rows counted
+// from the bottom.
+struct BoardMoves {
+    width: usize,
+    height: usize,
+    xs: Vec<usize>,
+    ys: Vec<usize>,
+}
+
+impl BoardMoves {
+    fn new() -> Self {
+        Self {
+            width: ROW_SIZE as usize,
+            height: ROW_SIZE as usize,
+            xs: Vec::new(),
+            ys: Vec::new(),
+        }
+    }
+
+    // Parses a `<w>x<h>` or `<w>:<h>` size, or a single number for square
+    // boards.
+    fn set_size(&mut self, size: &str) -> io::Result<()> {
+        let mut dims = size
+            .split(['x', ':'])
+            .map(|dim| dim.trim().parse::<usize>());
+        let parse = |dim: Option<Result<usize, _>>| {
+            dim.and_then(Result::ok)
+                .filter(|&dim| dim > 0)
+                .ok_or_else(|| invalid_data("bad board size"))
+        };
+
+        self.width = parse(dims.next())?;
+        self.height = match dims.next() {
+            None => self.width,
+            dim => parse(dim)?,
+        };
+        Ok(())
+    }
+
+    fn push(&mut self, x: usize, y: usize) {
+        self.xs.push(x);
+        self.ys.push(y);
+    }
+
+    fn into_squares(mut self) -> io::Result<Vec<Square>> {
+        fit_axis(self.width, &mut self.xs)?;
+        fit_axis(self.height, &mut self.ys)?;
+
+        self.xs
+            .into_iter()
+            .zip(self.ys)
+            .map(|(x, y)| square_at(x, y))
+            .collect()
+    }
+}
+
+impl Record {
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let format = Format::from_path(&path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unknown record format"))?;
+
+        Self::parse(&fs::read(path)?, format)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let format = Format::from_path(&path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unknown record format"))?;
+
+        fs::write(path, self.to_bytes(format)?)
+    }
+
+    pub fn parse(bytes: &[u8], format: Format) -> io::Result<Self> {
+        let record = match format {
+            Format::Psq => Self::parse_psq(&String::from_utf8_lossy(bytes))?,
+            Format::RenLib => Self::parse_renlib(bytes)?,
+            Format::Sgf => Self::parse_sgf(&String::from_utf8_lossy(bytes))?,
+        };
+
+        record.board()?;
+        Ok(record)
+    }
+
+    pub fn to_bytes(&self, format: Format) -> io::Result<Vec<u8>> {
+        match format {
+            Format::Psq => Ok(self.to_psq().into_bytes()),
+            Format::RenLib => self.to_renlib(),
+            Format::Sgf => Ok(self.to_sgf().into_bytes()),
+        }
+    }
+
+    // Plays the moves of the game, failing on moves to occupied squares.
+    pub fn board(&self) -> io::Result<Board> {
+        let mut board = Board::new();
+
+        for &mv in self.moves.iter() {
+            if board.stone_at(mv) != Stone::Empty {
+                return Err(invalid_data("move to an occupied square"));
+            }
+
+            board.push(mv);
+        }
+
+        Ok(board)
+    }
+
+    // The result given by the record, or else the one of the board if the
+    // last move made a five.
+    pub fn outcome(&self) -> Option<GameResult> {
+        let last = *self.moves.last()?;
+        let board = self.board().ok()?;
+
+        self.result.or_else(|| {
+            (CrossAlignment::from(&board, last) == CrossAlignment::Five).then(|| {
+                match board.stone_at(last) {
+                    Stone::Black => GameResult::BlackWins,
+                    _ => GameResult::WhiteWins,
+                }
+            })
+        })
+    }
+
+    // Piskvork records start with a `Piskvorky <w>x<h>, <x>:<y>, <n>` header,
+    // followed by one `x,y,time` line per move with 1-based coordinates, and
+    // optionally by `-1` and the names of the players. Boards of other sizes
+    // are placed on ours by `fit_axis`.
+    fn parse_psq(text: &str) -> io::Result<Self> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        let header = lines.next().ok_or_else(|| invalid_data("empty record"))?;
+        let size = header
+            .split([' ', ','])
+            .find(|token| token.contains('x'))
+            .ok_or_else(|| invalid_data("missing board size"))?;
+
+        let mut record = Self::default();
+        let mut moves = BoardMoves::new();
+        let mut names = Vec::new();
+
+        moves.set_size(size)?;
+
+        for line in lines {
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+
+            match fields.as_slice() {
+                [x, y, ..] if names.is_empty() => {
+                    let x = x.parse::<usize>().map_err(|_| invalid_data("bad move"))?;
+                    let y = y.parse::<usize>().map_err(|_| invalid_data("bad move"))?;
+
+                    moves.push(x.wrapping_sub(1), y.wrapping_sub(1));
+                }
+                ["-1"] => (),
+                [name] if name.parse::<i64>().is_err() => names.push(name.to_string()),
+                _ => (),
+            }
+        }
+
+        let mut names = names.into_iter();
+
+        record.moves = moves.into_squares()?;
+        record.black = names.next();
+        record.white = names.next();
+        Ok(record)
+    }
+
+    fn to_psq(&self) -> String {
+        let mut text = format!("Piskvorky {}x{}, 1:1, 0\n", ROW_SIZE, ROW_SIZE);
+
+        for mv in self.moves.iter() {
+            text += &format!("{},{},0\n", mv.file().value() + 1, mv.rank().value() + 1);
+        }
+
+        if self.black.is_some() || self.white.is_some() {
+            text += "-1\n";
+
+            for name in [&self.black, &self.white] {
+                text += &format!("{}\n", name.as_deref().unwrap_or("?"));
+            }
+        }
+
+        text
+    }
+
+    // Only the main line of the game tree is read, that is the first
+    // variation at each node. Properties are `SZ`, `PB`, `PW`, `RE` and `RU`,
+    // moves `B[..]` and `W[..]` with the column then the row as letters from
+    // `a`, the rows being counted from the top.
+    fn parse_sgf(text: &str) -> io::Result<Self> {
+        let mut record = Self::default();
+        let mut moves = BoardMoves::new();
+        let mut chars = text.chars().skip_while(|&c| c != '(').skip(1).peekable();
+        let mut ident = String::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                // The main line goes on in the first variation and ends with it.
+                '(' => (),
+                ')' => break,
+                '[' => {
+                    let mut value = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('\\') => value.extend(chars.next()),
+                            Some(']') => break,
+                            Some(c) => value.push(c),
+                            None => return Err(invalid_data("unterminated property")),
+                        }
+                    }
+
+                    record.set_sgf_property(&mut moves, &ident, &value)?;
+
+                    // Further values of the same property keep its identifier.
+                    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                        chars.next();
+                    }
+
+                    if chars.peek() != Some(&'[') {
+                        ident.clear();
+                    }
+                }
+                c if c.is_ascii_uppercase() => ident.push(c),
+                _ => (),
+            }
+        }
+
+        // The rows are only known to be counted from the top once the size
+        // has been read.
+        moves.ys = moves
+            .ys
+            .iter()
+            .map(|&y| (moves.height - 1).wrapping_sub(y))
+            .collect();
+        record.moves = moves.into_squares()?;
+        Ok(record)
+    }
+
+    fn set_sgf_property(
+        &mut self,
+        moves: &mut BoardMoves,
+        ident: &str,
+        value: &str,
+    ) -> io::Result<()> {
+        match ident {
+            "GM" if value != "4" => return Err(invalid_data("not a gomoku record")),
+            "SZ" => moves.set_size(value)?,
+            "PB" => self.black = Some(value.to_string()),
+            "PW" => self.white = Some(value.to_string()),
+            "RU" => self.rules = Some(value.to_string()),
+            "RE" => {
+                self.result = match value.chars().next() {
+                    Some('B') => Some(GameResult::BlackWins),
+                    Some('W') => Some(GameResult::WhiteWins),
+                    Some('0') | Some('D') => Some(GameResult::Draw),
+                    _ => None,
+                }
+            }
+            "B" | "W" => {
+                let coords = value.as_bytes();
+
+                if coords.len() != 2 {
+                    return Err(invalid_data("bad move"));
+                }
+
+                moves.push(
+                    coords[0].wrapping_sub(b'a') as usize,
+                    coords[1].wrapping_sub(b'a') as usize,
+                );
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn to_sgf(&self) -> String {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace(']', "\\]");
+        let mut text = format!("(;GM[4]FF[4]SZ[{}]", ROW_SIZE);
+
+        if let Some(black) = &self.black {
+            text += &format!("PB[{}]", escape(black));
+        }
+        if let Some(white) = &self.white {
+            text += &format!("PW[{}]", escape(white));
+        }
+        if let Some(rules) = &self.rules {
+            text += &format!("RU[{}]", escape(rules));
+        }
+        if let Some(result) = self.result {
+            text += match result {
+                GameResult::BlackWins => "RE[B+]",
+                GameResult::WhiteWins => "RE[W+]",
+                GameResult::Draw => "RE[0]",
+            };
+        }
+
+        for (idx, mv) in self.moves.iter().enumerate() {
+            text += &format!(
+                ";{}[{}{}]",
+                if idx % 2 == 0 { 'B' } else { 'W' },
+                (b'a' + mv.file().value()) as char,
+                (b'a' + (ROW_SIZE as u8 - 1 - mv.rank().value())) as char
+            );
+        }
+
+        text + ")\n"
+    }
+
+    // RenLib files hold a tree of 15x15 positions: a 20-byte header, then one
+    // node per move in depth-first order, made of the move byte (the column
+    // from 1 in the low nibble, the row from 0 in the high nibble, 0 for no
+    // move) and a flags byte, possibly followed by comment strings. Only the
+    // main line is read, and the 15x15 board is centred on ours.
+    fn parse_renlib(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < RENLIB_HEADER_SIZE || bytes[..8] != RENLIB_MAGIC {
+            return Err(invalid_data("not a RenLib file"));
+        }
+
+        let mut record = Self::default();
+        let mut pos = RENLIB_HEADER_SIZE;
+
+        while pos + 2 <= bytes.len() {
+            let (mv, flags) = (bytes[pos], bytes[pos + 1]);
+
+            pos += 2;
+
+            if flags & RENLIB_EXTENSION != 0 {
+                return Err(invalid_data("unsupported RenLib extension"));
+            }
+
+            if mv != 0 {
+                let (x, y) = ((mv & 0x0F) as usize, (mv >> 4) as usize);
+
+                if x == 0 || y >= 15 {
+                    return Err(invalid_data("bad move"));
+                }
+
+                record
+                    .moves
+                    .push(square_at(x - 1 + RENLIB_OFFSET, y + RENLIB_OFFSET)?);
+            }
+
+            // Comments are null-terminated strings following the node.
+            if flags & (RENLIB_COMMENT | RENLIB_OLD_COMMENT) != 0 {
+                while pos < bytes.len() && bytes[pos] != 0 {
+                    pos += 1;
+                }
+
+                pos += 1;
+            }
+
+            if flags & RENLIB_DOWN == 0 {
+                break;
+            }
+        }
+
+        Ok(record)
+    }
+
+    fn to_renlib(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = RENLIB_MAGIC.to_vec();
+
+        bytes.extend_from_slice(&[0xFF; RENLIB_HEADER_SIZE - 8]);
+
+        for (idx, mv) in self.moves.iter().enumerate() {
+            let (x, y) = (
+                (mv.file().value() as usize).wrapping_sub(RENLIB_OFFSET),
+                (mv.rank().value() as usize).wrapping_sub(RENLIB_OFFSET),
+            );
+
+            if x >= 15 || y >= 15 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "move outside of the RenLib board",
+                ));
+            }
+
+            bytes.push((y << 4 | (x + 1)) as u8);
+            bytes.push(if idx + 1 < self.moves.len() {
+                RENLIB_DOWN
+            } else {
+                0
+            });
+        }
+
+        Ok(bytes)
+    }
+}
+
+const RENLIB_MAGIC: [u8; 8] = [0xFF, b'R', b'e', b'n', b'L', b'i', b'b', 0xFF];
+const RENLIB_HEADER_SIZE: usize = 20;
+const RENLIB_OFFSET: usize = (ROW_SIZE as usize - 15) / 2;
+const RENLIB_DOWN: u8 = 0x80;
+const RENLIB_OLD_COMMENT: u8 = 0x20;
+const RENLIB_COMMENT: u8 = 0x08;
+const RENLIB_EXTENSION: u8 = 0x01;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Record {
+        Record {
+            moves: ["j10", "k11", "k10", "i10", "l10"]
+                .iter()
+                .map(|s| s.parse().unwrap())
+                .collect(),
+            black: Some(String::from("Kinko")),
+            white: Some(String::from("Other [engine]")),
+            result: Some(GameResult::BlackWins),
+            rules: Some(String::from("freestyle")),
+        }
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let record = sample();
+        let sgf = Record::parse(&record.to_bytes(Format::Sgf).unwrap(), Format::Sgf).unwrap();
+        let psq = Record::parse(&record.to_bytes(Format::Psq).unwrap(), Format::Psq).unwrap();
+        let lib = Record::parse(&record.to_bytes(Format::RenLib).unwrap(), Format::RenLib).unwrap();
+
+        assert_eq!(sgf, record);
+        assert_eq!(psq.moves, record.moves);
+        assert_eq!((psq.black, psq.white), (record.black, record.white));
+        assert_eq!(lib.moves, record.moves);
+    }
+
+    #[test]
+    fn sgf_main_line_only() {
+        let text = "(;GM[4]SZ[19]RE[W+R];B[jj];W[ki](;B[kj];W[ij])(;B[aa]))";
+        let record = Record::parse(text.as_bytes(), Format::Sgf).unwrap();
+
+        assert_eq!(record.moves.len(), 4);
+        assert_eq!(record.moves[1], "k11".parse().unwrap());
+        assert_eq!(record.result, Some(GameResult::WhiteWins));
+    }
+
+    #[test]
+    fn psq_coordinates() {
+        let text = "Piskvorky 19x19, 11:11, 0\n10,10,0\n11,9,0\n-1\n";
+        let record = Record::parse(text.as_bytes(), Format::Psq).unwrap();
+
+        assert_eq!(
+            record.moves,
+            vec!["j10".parse().unwrap(), "k09".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn other_board_sizes() {
+        let psq = |text: &str| Record::parse(text.as_bytes(), Format::Psq);
+        let sgf = |text: &str| Record::parse(text.as_bytes(), Format::Sgf);
+        let square = |s: &str| s.parse::<Square>().unwrap();
+
+        // The centre of a 15x15 board is the centre of ours.
+        assert_eq!(
+            psq("Piskvorky 15x15, 1:1, 0\n8,8,0\n").unwrap().moves,
+            vec![square("j10")]
+        );
+        assert_eq!(
+            sgf("(;GM[4]SZ[15];B[hh])").unwrap().moves,
+            vec![square("j10")]
+        );
+        assert_eq!(
+            sgf("(;GM[4]SZ[15];B[aa])").unwrap().moves,
+            vec![square("c17")]
+        );
+
+        // 20x20 games are kept in place if they fit, and shifted otherwise.
+        let text = "Piskvorky 20x20, 1:1, 0\n1,1,0\n19,19,0\n";
+
+        assert_eq!(psq(text).unwrap().moves, vec![square("a01"), square("s19")]);
+        assert_eq!(
+            psq("Piskvorky 20x20, 1:1, 0\n20,20,0\n2,10,0\n")
+                .unwrap()
+                .moves,
+            vec![square("s19"), square("a09")]
+        );
+        assert!(psq("Piskvorky 20x20, 1:1, 0\n1,1,0\n20,20,0\n").is_err());
+        assert!(psq("Piskvorky 15x15, 1:1, 0\n16,1,0\n").is_err());
+    }
+}
@@ -37,12 +37,10 @@ impl FromStr for File {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 1 {
-            return Err(());
+        match s.as_bytes() {
+            [file @ b'a'..=b's'] => Ok(File::new(file - b'a')),
+            _ => Err(()),
         }
-        Ok(File::new(
-            <u8>::try_from(s.chars().next().ok_or(())?).map_err(|_| ())? - 97,
-        ))
     }
 }
 
@@ -105,7 +103,10 @@ impl FromStr for Rank {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Rank::new(s.parse().map_err(|_| ())?) - 1)
+        match s.parse::<u8>() {
+            Ok(rank @ 1..=19) => Ok(Rank::new(rank - 1)),
+            _ => Err(()),
+        }
     }
 }
 
@@ -199,6 +200,23 @@ impl Square {
         self.0 < SQUARE_COUNT
     }
 
+    pub fn transform(self, sym: Symmetry) -> Self {
+        let last = ROW_SIZE as u8 - 1;
+        let (mut file, mut rank) = (self.file().value(), self.rank().value());
+
+        if sym.0 & 4 != 0 {
+            (file, rank) = (rank, file);
+        }
+        if sym.0 & 1 != 0 {
+            file = last - file;
+        }
+        if sym.0 & 2 != 0 {
+            rank = last - rank;
+        }
+
+        Self::from(File::new(file), Rank::new(rank))
+    }
+
     pub fn distance(self, other: Self) -> u8 {
         let file_distance = self.file().value().abs_diff(other.file().value());
         let rank_distance = self.rank().value().abs_diff(other.rank().value());
@@ -252,6 +270,42 @@ impl SubAssign<u16> for Square {
     }
 }
 
+// The 8 symmetries of the board, encoded on 3 bits: bit 2 transposes the
+// board (swapping files and ranks), then bit 0 mirrors the files and bit 1
+// mirrors the ranks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Symmetry(u8);
+
+impl Symmetry {
+    pub const IDENTITY: Self = Self(0);
+    pub const ALL: [Self; 8] = [
+        Self(0),
+        Self(1),
+        Self(2),
+        Self(3),
+        Self(4),
+        Self(5),
+        Self(6),
+        Self(7),
+    ];
+
+    pub fn new(value: u8) -> Self {
+        Self(value & 7)
+    }
+
+    pub fn value(self) -> u8 {
+        self.0
+    }
+
+    pub fn inverse(self) -> Self {
+        if self.0 & 4 != 0 {
+            Self(4 | (self.0 & 1) << 1 | (self.0 & 2) >> 1)
+        } else {
+            self
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Stone {
     Empty,
@@ -282,3 +336,45 @@ impl fmt::Display for Stone {
         write!(f, "{}", c)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_squares() {
+        for sq in (0..SQUARE_COUNT).map(Square::new) {
+            assert_eq!(sq.to_string().parse::<Square>(), Ok(sq));
+        }
+
+        for s in [
+            "", "a", "A01", "a00", "a0", "a20", "t01", "é01", "a-1", "a01x",
+        ] {
+            assert!(s.parse::<Square>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_every_symmetry() {
+        for sym in Symmetry::ALL {
+            let images = (0..SQUARE_COUNT)
+                .map(|idx| Square::new(idx).transform(sym))
+                .collect::<Vec<_>>();
+
+            for (idx, &image) in images.iter().enumerate() {
+                assert!(image.is_valid());
+                assert_eq!(image.transform(sym.inverse()), Square::new(idx as u16));
+            }
+
+            assert_eq!(sym.inverse().inverse(), sym);
+        }
+
+        // The symmetries are all distinct: the images of a square off every
+        // axis differ.
+        let sq: Square = "b05".parse().unwrap();
+        let mut images = Symmetry::ALL.map(|sym| sq.transform(sym).value());
+
+        images.sort();
+        assert!(images.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use rand::{rngs::ThreadRng, Rng};
+
+use crate::core::{
+    board::Board,
+    types::{Square, Stone},
+};
+
+lazy_static! {
+    pub static ref BOOK: RwLock<Option<Arc<Book>>> = RwLock::new(None);
+}
+
+// The book file is little-endian: the magic bytes, the entry count as a u64,
+// then the entries sorted by key, each made of the position key as a u64, the
+// move as a u16 and its weight as a u16. Positions are stored in their
+// canonical orientation among the 8 board symmetries, and so are their moves.
+const MAGIC: &[u8; 8] = b"KINKOBK2";
+const ENTRY_SIZE: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Entry {
+    key: u64,
+    mv: Square,
+    weight: u16,
+}
+
+#[derive(Debug, Default)]
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+impl Book {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 16 || &bytes[..8] != MAGIC {
+            return Err(invalid_data("not a book file"));
+        }
+
+        let count = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        let body = &bytes[16..];
+
+        if body.len() != count * ENTRY_SIZE {
+            return Err(invalid_data("book file has the wrong size"));
+        }
+
+        let entries = body
+            .chunks_exact(ENTRY_SIZE)
+            .map(|c| Entry {
+                key: u64::from_le_bytes(c[..8].try_into().unwrap()),
+                mv: Square::new(u16::from_le_bytes([c[8], c[9]])),
+                weight: u16::from_le_bytes([c[10], c[11]]),
+            })
+            .collect::<Vec<_>>();
+
+        if entries.iter().any(|entry| !entry.mv.is_valid())
+            || entries.windows(2).any(|w| w[0].key > w[1].key)
+        {
+            return Err(invalid_data("corrupted book file"));
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(16 + self.entries.len() * ENTRY_SIZE);
+
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+
+        for entry in self.entries.iter() {
+            bytes.extend_from_slice(&entry.key.to_le_bytes());
+            bytes.extend_from_slice(&entry.mv.value().to_le_bytes());
+            bytes.extend_from_slice(&entry.weight.to_le_bytes());
+        }
+
+        fs::write(path, bytes)
+    }
+
+    // Builds a book from games given as move lists from the empty board,
+    // keeping the moves played at least `min_count` times in the first
+    // `max_plies` plies, weighted by how often they were played.
+    pub fn from_games(games: &[Vec<Square>], max_plies: usize, min_count: u32) -> Self {
+        let mut counts = HashMap::<(u64, u16), u32>::new();
+
+        for game in games {
+            let mut board = Board::new();
+
+            for &mv in game.iter().take(max_plies) {
+                if !mv.is_valid() || board.stone_at(mv) != Stone::Empty {
+                    break;
+                }
+
+                let (canonical, sym) = board.canonical();
+
+                *counts
+                    .entry((canonical.key(), mv.transform(sym).value()))
+                    .or_default() += 1;
+                board.push(mv);
+            }
+        }
+
+        let mut entries = counts
+            .into_iter()
+            .filter(|&(_, count)| count >= min_count)
+            .map(|((key, mv), count)| Entry {
+                key,
+                mv: Square::new(mv),
+                weight: count.min(u16::MAX as u32) as u16,
+            })
+            .collect::<Vec<_>>();
+
+        entries.sort_by_key(|entry| (entry.key, entry.mv.value()));
+        Self { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Returns the book moves of the position with their weights, in the
+    // orientation of the given board.
+    pub fn moves(&self, board: &Board) -> Vec<(Square, u16)> {
+        let (canonical, sym) = board.canonical();
+        let key = canonical.key();
+        let start = self.entries.partition_point(|entry| entry.key < key);
+
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .map(|entry| (entry.mv.transform(sym.inverse()), entry.weight))
+            .filter(|&(mv, _)| board.stone_at(mv) == Stone::Empty)
+            .collect()
+    }
+
+    // Picks one of the book moves of the position at random, proportionally
+    // to their weights.
+    pub fn pick(&self, board: &Board) -> Option<Square> {
+        let moves = self.moves(board);
+        let total = moves.iter().map(|&(_, w)| w as u32).sum::<u32>();
+
+        if total == 0 {
+            return None;
+        }
+
+        let mut target = ThreadRng::default().gen_range(0..total);
+
+        moves.into_iter().find_map(|(mv, weight)| {
+            if target < weight as u32 {
+                Some(mv)
+            } else {
+                target -= weight as u32;
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::Symmetry;
+
+    fn game(moves: &str) -> Vec<Square> {
+        moves
+            .split_ascii_whitespace()
+            .map(|mv| mv.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn finds_moves_in_symmetric_positions() {
+        let opening = game("c03 d04 c04");
+        let reply: Square = "e05".parse().unwrap();
+        let book = Book::from_games(&[[opening.clone(), vec![reply]].concat()], 4, 1);
+
+        for &sym in Symmetry::ALL.iter() {
+            let mut board = Board::new();
+
+            for &mv in opening.iter() {
+                board.push(mv.transform(sym));
+            }
+
+            assert_eq!(book.moves(&board), vec![(reply.transform(sym), 1)]);
+            assert_eq!(book.pick(&board), Some(reply.transform(sym)));
+        }
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let book = Book::from_games(
+            &[
+                game("j10 k11 k10 j11"),
+                game("j10 k11 k10 l10"),
+                game("a01 b02"),
+            ],
+            3,
+            1,
+        );
+        let path = std::env::temp_dir().join(format!("kinko-book-{}.bin", std::process::id()));
+
+        book.save(&path).unwrap();
+
+        let loaded = Book::load(&path);
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().entries, book.entries);
+        assert!(Book::from_bytes(b"KINKOBK1\0\0\0\0\0\0\0\0").is_err());
+    }
+}
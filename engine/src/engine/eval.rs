@@ -1,37 +1,304 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use super::nnue::{Accumulator, Network};
+use super::options::OPTIONS;
 use super::score::Score;
-use crate::core::{bitboard::BitboardIter, board::Board, magic::CrossAlignment, types::Square};
+use crate::core::{
+    bitboard::BitboardIter,
+    board::Board,
+    magic::{Alignment, CrossAlignment, OwnedAlignment, LINES},
+    types::{Square, Stone},
+};
+
+lazy_static! {
+    pub static ref EVAL_PARAMS: RwLock<EvalParams> = RwLock::new(EvalParams::new());
+    pub static ref EVAL_NETWORK: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+}
+
+// Evaluation weights in centipoints. Line weights are applied once per
+// alignment found in a single direction (on the stone owning it), cross
+// weights once per stone depending on the combination of its four lines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    line: [i16; Alignment::LIST.len()],
+    cross: [i16; CrossAlignment::LIST.len()],
+    tempo: i16,
+}
+
+impl EvalParams {
+    // Bound of the weights settable as options, keeping evaluations of usual
+    // positions far from the mate scores.
+    pub const MAX_WEIGHT: i16 = 2000;
+
+    pub fn new() -> Self {
+        Self {
+            line: [0; Alignment::LIST.len()],
+            cross: [0, 5, 20, 15, 240, 40, 190, 110, 300, 380, 510],
+            tempo: 2,
+        }
+    }
+
+    pub fn line(&self, align: Alignment) -> Score {
+        Score::cp(self.line[align as usize])
+    }
+
+    pub fn cross(&self, align: CrossAlignment) -> Score {
+        Score::cp(self.cross[align as usize])
+    }
+
+    pub fn tempo(&self) -> Score {
+        Score::cp(self.tempo)
+    }
+
+    pub fn names() -> impl Iterator<Item = String> {
+        Alignment::LIST[1..]
+            .iter()
+            .map(|align| format!("Line{:?}", align))
+            .chain(
+                CrossAlignment::LIST[1..]
+                    .iter()
+                    .map(|align| format!("Cross{:?}", align)),
+            )
+            .chain(std::iter::once(String::from("Tempo")))
+    }
+
+    pub fn get(&self, name: &str) -> Option<i16> {
+        Self::names()
+            .position(|n| n == name)
+            .map(|idx| self.values()[idx])
+    }
+
+    pub fn set(&mut self, name: &str, value: i16) -> io::Result<()> {
+        let idx = Self::names()
+            .position(|n| n == name)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let mut values = self.values();
+
+        values[idx] = value;
+        self.set_values(&values);
+        Ok(())
+    }
+
+    // Flattened view of the weights, in the same order as `names()`.
+    pub fn values(&self) -> Vec<i16> {
+        self.line[1..]
+            .iter()
+            .chain(self.cross[1..].iter())
+            .chain(std::iter::once(&self.tempo))
+            .copied()
+            .collect()
+    }
+
+    pub fn set_values(&mut self, values: &[i16]) {
+        let (line, rest) = values.split_at(self.line.len() - 1);
+        let (cross, tempo) = rest.split_at(self.cross.len() - 1);
+
+        self.line[1..].copy_from_slice(line);
+        self.cross[1..].copy_from_slice(cross);
+        self.tempo = tempo[0];
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in Self::names().zip(self.values()) {
+            writeln!(f, "{} {}", name, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for EvalParams {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = Self::new();
+
+        for line in s.lines() {
+            let tokens = line
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .split_ascii_whitespace()
+                .collect::<Vec<_>>();
+
+            match tokens.as_slice() {
+                [] => (),
+                [name, value] => params.set(
+                    name,
+                    value
+                        .parse()
+                        .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?,
+                )?,
+                _ => return Err(io::Error::from(io::ErrorKind::InvalidData)),
+            }
+        }
+
+        Ok(params)
+    }
+}
 
 pub fn is_victory(board: &Board, sq: Square) -> bool {
     CrossAlignment::from(board, sq) == CrossAlignment::Five
 }
 
-pub fn score_alignment(alignment: CrossAlignment) -> Score {
-    match alignment {
-        CrossAlignment::Five => Score::cp(510),
-        CrossAlignment::FourFour => Score::cp(380),
-        CrossAlignment::OpenFourThree => Score::cp(300),
-        CrossAlignment::FourThree => Score::cp(110),
-        CrossAlignment::DoubleOpenThree => Score::cp(190),
-        CrossAlignment::DoubleThree => Score::cp(40),
-        CrossAlignment::OpenFour => Score::cp(240),
-        CrossAlignment::Four => Score::cp(15),
-        CrossAlignment::OpenThree => Score::cp(20),
-        CrossAlignment::Three => Score::cp(5),
-        CrossAlignment::NoAlign => Score::ZERO,
+// Returns the network searches should evaluate with, if network evaluation is
+// selected and a network has been loaded.
+pub fn active_network() -> Option<Arc<Network>> {
+    if OPTIONS.read().unwrap().check("UseNNUE") == Some(true) {
+        EVAL_NETWORK.read().unwrap().clone()
+    } else {
+        None
     }
 }
 
 pub fn evaluate(board: &Board) -> Score {
-    let mut our_score = Score::ZERO;
-    let mut their_score = Score::ZERO;
+    match active_network() {
+        Some(network) => Accumulator::new(network, board).evaluate(board.turn()),
+        None => evaluate_with(board, &EVAL_PARAMS.read().unwrap()),
+    }
+}
+
+pub fn evaluate_with(board: &Board, params: &EvalParams) -> Score {
+    side_score(board, board.turn(), params)
+        .saturating_sub(side_score(board, board.turn().flip(), params))
+        .saturating_add(params.tempo())
+}
 
-    for sq in BitboardIter::from_bitboard(&board.bitboard(board.turn())) {
-        our_score += score_alignment(CrossAlignment::from(board, sq));
+// Number of times each weight contributes to `evaluate_with()`, in the same
+// order as `EvalParams::names()`, so that the evaluation is the dot product of
+// the coefficients with `EvalParams::values()`.
+pub fn coefficients(board: &Board) -> Vec<i16> {
+    let line_count = Alignment::LIST.len() - 1;
+    let mut coeffs = vec![0; EvalParams::names().count()];
+
+    for (side, sign) in [(board.turn(), 1), (board.turn().flip(), -1)] {
+        for sq in BitboardIter::from_bitboard(&board.bitboard(side)) {
+            let lines = LINES.map(|(dir, opp_dir)| OwnedAlignment::from(board, sq, dir, opp_dir));
+
+            for line in lines.iter().filter(|line| line.owned()) {
+                coeffs[line.align() as usize - 1] += sign;
+            }
+
+            let cross = CrossAlignment::from_lines(lines.map(|line| line.align()));
+
+            if cross != CrossAlignment::NoAlign {
+                coeffs[line_count + cross as usize - 1] += sign;
+            }
+        }
     }
 
-    for sq in BitboardIter::from_bitboard(&board.bitboard(board.turn().flip())) {
-        their_score += score_alignment(CrossAlignment::from(board, sq));
+    *coeffs.last_mut().unwrap() = 1;
+    coeffs
+}
+
+fn side_score(board: &Board, side: Stone, params: &EvalParams) -> Score {
+    let mut score = Score::ZERO;
+
+    for sq in BitboardIter::from_bitboard(&board.bitboard(side)) {
+        let lines = LINES.map(|(dir, opp_dir)| OwnedAlignment::from(board, sq, dir, opp_dir));
+
+        for line in lines.iter().filter(|line| line.owned()) {
+            score = score.saturating_add(params.line(line.align()));
+        }
+
+        score = score.saturating_add(
+            params.cross(CrossAlignment::from_lines(lines.map(|line| line.align()))),
+        );
     }
 
-    our_score - their_score + Score::cp(2)
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::score::ScoreKind;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn coefficients_dotted_with_weights_evaluate() {
+        let mut rng = StdRng::seed_from_u64(0x636f656666);
+
+        for _ in 0..200 {
+            let mut board = Board::new();
+            let mut params = EvalParams::new();
+
+            for _ in 0..rng.gen_range(0..80) {
+                let sq = Square::new(rng.gen_range(0..100) / 10 * 19 + rng.gen_range(0..10));
+
+                if board.stone_at(sq) == Stone::Empty {
+                    board.push(sq);
+                }
+            }
+
+            let values = (0..params.values().len())
+                .map(|_| rng.gen_range(-300..300))
+                .collect::<Vec<i16>>();
+
+            params.set_values(&values);
+
+            let dot = coefficients(&board)
+                .iter()
+                .zip(values.iter())
+                .map(|(&c, &w)| c as i32 * w as i32)
+                .sum::<i32>();
+
+            assert_eq!(dot, evaluate_with(&board, &params).value() as i32);
+        }
+    }
+
+    #[test]
+    fn extreme_weights_do_not_reach_mate_scores() {
+        let mut board = Board::new();
+
+        // Stripes of four stones, so that no five is on the board.
+        for idx in 0..300 {
+            let (rank, file) = (idx / 19, idx % 19);
+            let black = (file / 4 + rank) % 2 == 0;
+
+            if black != (board.turn() == Stone::Black) {
+                board.push_null();
+            }
+
+            board.push(Square::new(idx));
+        }
+
+        for weight in [i16::MAX, i16::MIN + 1, EvalParams::MAX_WEIGHT] {
+            let mut params = EvalParams::new();
+
+            params.set_values(&vec![weight; params.values().len()]);
+
+            for _ in 0..2 {
+                let score = evaluate_with(&board, &params);
+
+                assert!(
+                    matches!(score.kind(), ScoreKind::Centipoint(_)),
+                    "{}",
+                    score
+                );
+                board.push_null();
+            }
+        }
+    }
 }
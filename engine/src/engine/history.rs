@@ -0,0 +1,74 @@
+use crate::core::types::{Square, Stone, BOARD_SIZE};
+
+const HISTORY_MAX: i32 = 16384;
+
+// Move ordering statistics gathered from beta cutoffs during a search: two
+// killer moves per ply, and a butterfly history table indexed by side and
+// square.
+pub struct History {
+    butterfly: Box<[[i32; BOARD_SIZE]; 2]>,
+    killers: Vec<[Option<Square>; 2]>,
+}
+
+fn side_index(side: Stone) -> usize {
+    if side == Stone::White {
+        1
+    } else {
+        0
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            butterfly: Box::new([[0; BOARD_SIZE]; 2]),
+            killers: Vec::new(),
+        }
+    }
+
+    pub fn score(&self, side: Stone, sq: Square) -> i32 {
+        self.butterfly[side_index(side)][sq.value() as usize]
+    }
+
+    pub fn is_killer(&self, ply: u16, sq: Square) -> bool {
+        self.killers
+            .get(ply as usize)
+            .is_some_and(|killers| killers.contains(&Some(sq)))
+    }
+
+    fn add_bonus(&mut self, side: Stone, sq: Square, bonus: i32) {
+        let entry = &mut self.butterfly[side_index(side)][sq.value() as usize];
+
+        // Gravity keeps the entries within [-HISTORY_MAX, HISTORY_MAX].
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+
+    // Rewards the move causing a beta cutoff and penalizes the moves searched
+    // before it at the same node.
+    pub fn update(&mut self, side: Stone, ply: u16, cutoff: Square, tried: &[Square], depth: u16) {
+        let bonus = (depth as i32 * depth as i32).min(HISTORY_MAX);
+
+        self.add_bonus(side, cutoff, bonus);
+
+        for &sq in tried {
+            self.add_bonus(side, sq, -bonus);
+        }
+
+        if self.killers.len() <= ply as usize {
+            self.killers.resize(ply as usize + 1, [None; 2]);
+        }
+
+        let killers = &mut self.killers[ply as usize];
+
+        if killers[0] != Some(cutoff) {
+            killers[1] = killers[0];
+            killers[0] = Some(cutoff);
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,4 +1,10 @@
+pub mod book;
 pub mod eval;
+pub mod history;
 pub mod movegen;
+pub mod nnue;
+pub mod options;
 pub mod score;
 pub mod search;
+pub mod tt;
+pub mod vcf;
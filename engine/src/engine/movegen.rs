@@ -1,18 +1,57 @@
 use permutation::permutation;
 use rand::{rngs::ThreadRng, RngCore};
 
+use super::history::History;
 use crate::core::{
     bitboard::{Bitboard, BitboardIter},
     board::Board,
-    magic::CrossAlignment,
+    magic::{self, CrossAlignment},
     types::{File, Rank, Square, Stone, BOARD_SIZE},
 };
 
 use std::fmt;
 
+// One-move tactics, detected from the pattern tables: a five for the side to
+// move, a single opponent five-threat to block, or several of them, in which
+// case the game is lost whatever is played (the square is only one of the
+// blocks, so that a move can still be returned).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tactic {
+    Win(Square),
+    Block(Square),
+    Lost(Square),
+}
+
+impl Tactic {
+    pub fn detect(board: &Board) -> Option<Self> {
+        let wins = magic::side_fives(board, board.turn());
+
+        if let Some(sq) = BitboardIter::from_bitboard(&wins).next() {
+            return Some(Self::Win(sq));
+        }
+
+        let blocks = magic::side_fives(board, board.turn().flip());
+        let sq = BitboardIter::from_bitboard(&blocks).next()?;
+
+        if blocks.len() == 1 {
+            Some(Self::Block(sq))
+        } else {
+            Some(Self::Lost(sq))
+        }
+    }
+
+    pub fn square(&self) -> Square {
+        match *self {
+            Self::Win(sq) | Self::Block(sq) | Self::Lost(sq) => sq,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Movegen {
     move_list: Vec<Square>,
+    forcing: Vec<bool>,
+    patterns: Vec<i32>,
     move_idx: usize,
 }
 
@@ -20,6 +59,8 @@ impl Movegen {
     pub fn new() -> Self {
         Self {
             move_list: Vec::with_capacity(BOARD_SIZE),
+            forcing: Vec::new(),
+            patterns: Vec::new(),
             move_idx: 0,
         }
     }
@@ -60,6 +101,16 @@ impl Movegen {
         self.generate_from_mask(mask);
     }
 
+    // Restricts the move list to a single move, such as the square of a
+    // one-move tactic.
+    pub fn restrict_to(&mut self, mv: Square) {
+        self.move_list.clear();
+        self.move_list.push(mv);
+        self.forcing = vec![true];
+        self.patterns = vec![0];
+        self.move_idx = 0;
+    }
+
     pub fn generate_all(&mut self, board: &Board) {
         self.generate_from_mask(board.bitboard(Stone::Empty));
     }
@@ -71,20 +122,78 @@ impl Movegen {
     }
 
     pub fn select_random_move(&self) -> Square {
-        let mut rng = ThreadRng::default();
+        self.select_random_move_with(&mut ThreadRng::default())
+    }
 
+    pub fn select_random_move_with<R: RngCore>(&self, rng: &mut R) -> Square {
         self.move_list[rng.next_u64() as usize % self.move_list.len()]
     }
 
     pub fn order_moves(&mut self, board: &Board) {
-        let move_aligns = self
+        self.order_moves_with(board, None, 0);
+    }
+
+    // Orders moves by the strongest pattern they create for us or deny to the
+    // opponent (ours first on ties), then killers, then history. Also flags
+    // forcing moves, that is moves creating at least an open three for us or
+    // preventing at least a four for the opponent, and keeps the pattern
+    // strength of each move.
+    pub fn order_moves_with(&mut self, board: &Board, history: Option<&History>, ply: u16) {
+        let turn = board.turn();
+        let mut forcing = Vec::with_capacity(self.move_list.len());
+        let move_keys = self
             .move_list
             .iter()
-            .map(|&sq| CrossAlignment::after(board, sq, board.turn()))
+            .map(|&sq| {
+                let ours = CrossAlignment::after(board, sq, turn);
+                let theirs = CrossAlignment::after(board, sq, turn.flip());
+                let pattern = (ours as i32 * 2).max(theirs as i32 * 2 - 1);
+
+                forcing.push(ours >= CrossAlignment::OpenThree || theirs >= CrossAlignment::Four);
+
+                match history {
+                    Some(history) => (pattern, history.is_killer(ply, sq), history.score(turn, sq)),
+                    None => (pattern, false, 0),
+                }
+            })
             .collect::<Vec<_>>();
-        let perm = permutation::sort_by(move_aligns, |a, b| b.cmp(a));
+        let patterns = move_keys.iter().map(|key| key.0).collect::<Vec<_>>();
+        let perm = permutation::sort_by(move_keys, |a, b| b.cmp(a));
 
         self.move_list = perm.apply_slice(&self.move_list);
+        self.forcing = perm.apply_slice(&forcing);
+        self.patterns = perm.apply_slice(&patterns);
+    }
+
+    // Returns whether the move last returned by the iterator is forcing, as
+    // flagged by `order_moves_with()`.
+    pub fn last_is_forcing(&self) -> bool {
+        self.move_idx
+            .checked_sub(1)
+            .and_then(|idx| self.forcing.get(idx))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    // Returns the pattern strength of the move last returned by the iterator,
+    // positive if it makes at least a three or blocks one of the opponent.
+    pub fn last_pattern(&self) -> i32 {
+        self.move_idx
+            .checked_sub(1)
+            .and_then(|idx| self.patterns.get(idx))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn prioritize(&mut self, mv: Square) {
+        if let Some(idx) = self.move_list.iter().position(|&sq| sq == mv) {
+            self.move_list[..=idx].rotate_right(1);
+
+            if idx < self.forcing.len() {
+                self.forcing[..=idx].rotate_right(1);
+                self.patterns[..=idx].rotate_right(1);
+            }
+        }
     }
 }
 
@@ -121,3 +230,110 @@ impl fmt::Display for Movegen {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::eval;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // Random positions with stones packed in a small area of the board, so
+    // that fours are common, and without a five yet.
+    fn random_board(rng: &mut StdRng) -> Board {
+        let mut board = Board::new();
+        let (file, rank) = (rng.gen_range(0..12u8), rng.gen_range(0..12u8));
+
+        for _ in 0..rng.gen_range(4..30) {
+            let sq = Square::from(
+                File::new(file + rng.gen_range(0..7)),
+                Rank::new(rank + rng.gen_range(0..7)),
+            );
+
+            if board.stone_at(sq) == Stone::Empty {
+                board.push(sq);
+
+                if eval::is_victory(&board, sq) {
+                    board.pop(sq);
+                }
+            }
+        }
+
+        board
+    }
+
+    // The empty squares where `side` completes a five, found by playing every
+    // one of them.
+    fn reference_fives(board: &Board, side: Stone) -> Vec<Square> {
+        BitboardIter::from_bitboard(&board.bitboard(Stone::Empty))
+            .filter(|&sq| {
+                let mut board = board.clone();
+
+                if board.turn() != side {
+                    board.push_null();
+                }
+
+                board.push(sq);
+                eval::is_victory(&board, sq)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detect_matches_playing_every_move() {
+        let mut rng = StdRng::seed_from_u64(0x7461637469);
+        let mut detected = 0;
+
+        for _ in 0..2000 {
+            let board = random_board(&mut rng);
+            let wins = reference_fives(&board, board.turn());
+            let blocks = reference_fives(&board, board.turn().flip());
+            let tactic = Tactic::detect(&board);
+
+            match tactic {
+                Some(Tactic::Win(sq)) => assert!(wins.contains(&sq), "{:?} on\n{}", tactic, board),
+                Some(Tactic::Block(sq)) => assert!(
+                    wins.is_empty() && blocks == [sq],
+                    "{:?} on\n{}",
+                    tactic,
+                    board
+                ),
+                Some(Tactic::Lost(sq)) => assert!(
+                    wins.is_empty() && blocks.len() > 1 && blocks.contains(&sq),
+                    "{:?} on\n{}",
+                    tactic,
+                    board
+                ),
+                None => assert!(
+                    wins.is_empty() && blocks.is_empty(),
+                    "no tactic on\n{}",
+                    board
+                ),
+            }
+
+            detected += tactic.is_some() as u32;
+        }
+
+        assert!(detected > 100, "only {} tactics", detected);
+    }
+
+    // Around a single black stone, no white move creates or denies a pattern,
+    // so killers and then history decide the order.
+    #[test]
+    fn killers_then_history_order_quiet_moves() {
+        let mut board = Board::new();
+        let mut history = History::new();
+        let (killer, good) = ("k09".parse().unwrap(), "i11".parse().unwrap());
+
+        board.push("j10".parse().unwrap());
+        history.update(Stone::White, 0, killer, &[], 2);
+        history.update(Stone::White, 5, good, &[], 8);
+
+        let mut movegen = Movegen::new();
+
+        movegen.generate_near(&board);
+        movegen.order_moves_with(&board, Some(&history), 0);
+
+        assert_eq!(movegen.next(), Some(killer));
+        assert_eq!(movegen.next(), Some(good));
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::score::Score;
+use crate::core::{
+    bitboard::BitboardIter,
+    board::Board,
+    types::{Square, Stone, BOARD_SIZE},
+};
+
+// The network has one input per (square, stone relative to the perspective)
+// pair, a hidden layer whose pre-activations are kept in an accumulator for
+// both perspectives, and a single output fed with the clipped hidden layer of
+// the side to move followed by the one of its opponent.
+//
+// The weights file is little-endian: the magic bytes, the hidden layer size as
+// a u32, then the input weights (input-major) and hidden biases as i16, the
+// output weights as i16 and finally the output bias as an i32.
+const MAGIC: &[u8; 8] = b"KINKONN1";
+const INPUTS: usize = BOARD_SIZE * 2;
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+pub struct Network {
+    hidden: usize,
+    input_weights: Vec<i16>,
+    hidden_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Network {{ hidden: {} }}", self.hidden)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+impl Network {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 12 || &bytes[..8] != MAGIC {
+            return Err(invalid_data("not a network file"));
+        }
+
+        let hidden = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+
+        if hidden == 0 || !hidden.is_multiple_of(16) {
+            return Err(invalid_data(
+                "hidden size must be a non-zero multiple of 16",
+            ));
+        }
+
+        let i16_count = INPUTS * hidden + hidden + 2 * hidden;
+        let body = &bytes[12..];
+
+        if body.len() != i16_count * 2 + 4 {
+            return Err(invalid_data("network file has the wrong size"));
+        }
+
+        let mut values = body[..i16_count * 2]
+            .chunks_exact(2)
+            .map(|c| i16::from_le_bytes([c[0], c[1]]));
+
+        Ok(Self {
+            hidden,
+            input_weights: values.by_ref().take(INPUTS * hidden).collect(),
+            hidden_biases: values.by_ref().take(hidden).collect(),
+            output_weights: values.by_ref().take(2 * hidden).collect(),
+            output_bias: i32::from_le_bytes(body[i16_count * 2..].try_into().unwrap()),
+        })
+    }
+
+    fn feature_weights(&self, sq: Square, stone: Stone, perspective: Stone) -> &[i16] {
+        let idx = sq.value() as usize + if stone == perspective { 0 } else { BOARD_SIZE };
+
+        &self.input_weights[idx * self.hidden..(idx + 1) * self.hidden]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    values: [Vec<i16>; 2],
+}
+
+impl Accumulator {
+    pub fn new(network: Arc<Network>, board: &Board) -> Self {
+        let mut acc = Self {
+            values: [network.hidden_biases.clone(), network.hidden_biases.clone()],
+            network,
+        };
+
+        for stone in [Stone::Black, Stone::White] {
+            for sq in BitboardIter::from_bitboard(&board.bitboard(stone)) {
+                acc.add_stone(sq, stone);
+            }
+        }
+
+        acc
+    }
+
+    pub fn add_stone(&mut self, sq: Square, stone: Stone) {
+        for (perspective, values) in [Stone::Black, Stone::White]
+            .into_iter()
+            .zip(self.values.iter_mut())
+        {
+            simd::add_assign(values, self.network.feature_weights(sq, stone, perspective));
+        }
+    }
+
+    pub fn remove_stone(&mut self, sq: Square, stone: Stone) {
+        for (perspective, values) in [Stone::Black, Stone::White]
+            .into_iter()
+            .zip(self.values.iter_mut())
+        {
+            simd::sub_assign(values, self.network.feature_weights(sq, stone, perspective));
+        }
+    }
+
+    pub fn evaluate(&self, turn: Stone) -> Score {
+        let (us, them) = match turn {
+            Stone::White => (&self.values[1], &self.values[0]),
+            _ => (&self.values[0], &self.values[1]),
+        };
+        let hidden = self.network.hidden;
+        let output = simd::crelu_dot(us, &self.network.output_weights[..hidden])
+            + simd::crelu_dot(them, &self.network.output_weights[hidden..])
+            + self.network.output_bias;
+
+        Score::cp((output as i64 * SCALE as i64 / (QA * QB) as i64).clamp(-30000, 30000) as i16)
+    }
+}
+
+mod simd {
+    use super::QA;
+
+    pub fn add_assign(values: &mut [i16], weights: &[i16]) {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked.
+            return unsafe { avx2::add_assign(values, weights) };
+        }
+
+        scalar_add_assign(values, weights)
+    }
+
+    pub fn sub_assign(values: &mut [i16], weights: &[i16]) {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked.
+            return unsafe { avx2::sub_assign(values, weights) };
+        }
+
+        scalar_sub_assign(values, weights)
+    }
+
+    pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked.
+            return unsafe { avx2::crelu_dot(values, weights) };
+        }
+
+        scalar_crelu_dot(values, weights)
+    }
+
+    pub fn scalar_add_assign(values: &mut [i16], weights: &[i16]) {
+        values
+            .iter_mut()
+            .zip(weights)
+            .for_each(|(v, &w)| *v = v.wrapping_add(w));
+    }
+
+    pub fn scalar_sub_assign(values: &mut [i16], weights: &[i16]) {
+        values
+            .iter_mut()
+            .zip(weights)
+            .for_each(|(v, &w)| *v = v.wrapping_sub(w));
+    }
+
+    pub fn scalar_crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        values
+            .iter()
+            .zip(weights)
+            .map(|(&v, &w)| (v as i32).clamp(0, QA) * w as i32)
+            .sum()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub mod avx2 {
+        use super::QA;
+        use std::arch::x86_64::*;
+
+        // All slices have a length that is a multiple of 16, which is checked
+        // when loading the network.
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn add_assign(values: &mut [i16], weights: &[i16]) {
+            for (v, w) in values.chunks_exact_mut(16).zip(weights.chunks_exact(16)) {
+                let a = _mm256_loadu_si256(v.as_ptr() as *const __m256i);
+                let b = _mm256_loadu_si256(w.as_ptr() as *const __m256i);
+
+                _mm256_storeu_si256(v.as_mut_ptr() as *mut __m256i, _mm256_add_epi16(a, b));
+            }
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn sub_assign(values: &mut [i16], weights: &[i16]) {
+            for (v, w) in values.chunks_exact_mut(16).zip(weights.chunks_exact(16)) {
+                let a = _mm256_loadu_si256(v.as_ptr() as *const __m256i);
+                let b = _mm256_loadu_si256(w.as_ptr() as *const __m256i);
+
+                _mm256_storeu_si256(v.as_mut_ptr() as *mut __m256i, _mm256_sub_epi16(a, b));
+            }
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+            let zero = _mm256_setzero_si256();
+            let max = _mm256_set1_epi16(QA as i16);
+            let mut sum = _mm256_setzero_si256();
+
+            for (v, w) in values.chunks_exact(16).zip(weights.chunks_exact(16)) {
+                let a = _mm256_loadu_si256(v.as_ptr() as *const __m256i);
+                let b = _mm256_loadu_si256(w.as_ptr() as *const __m256i);
+                let clipped = _mm256_min_epi16(_mm256_max_epi16(a, zero), max);
+
+                sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, b));
+            }
+
+            let mut lanes = [0i32; 8];
+
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+            lanes.iter().sum()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_values(rng: &mut StdRng, len: usize) -> Vec<i16> {
+        (0..len).map(|_| rng.gen()).collect()
+    }
+
+    fn random_network(rng: &mut StdRng, hidden: usize) -> Network {
+        let mut bytes = MAGIC.to_vec();
+
+        bytes.extend((hidden as u32).to_le_bytes());
+
+        for _ in 0..(INPUTS + 3) * hidden {
+            bytes.extend(rng.gen_range(-64i16..64).to_le_bytes());
+        }
+
+        bytes.extend(rng.gen_range(-1000i32..1000).to_le_bytes());
+        Network::from_bytes(&bytes).unwrap()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_matches_scalar() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let mut rng = StdRng::seed_from_u64(0x73696d64);
+
+        for len in [16, 64, 256] {
+            let values = random_values(&mut rng, len);
+            let weights = random_values(&mut rng, len);
+
+            // SAFETY: AVX2 support was just checked.
+            unsafe {
+                assert_eq!(
+                    simd::avx2::crelu_dot(&values, &weights),
+                    simd::scalar_crelu_dot(&values, &weights)
+                );
+
+                let (mut fast, mut slow) = (values.clone(), values.clone());
+
+                simd::avx2::add_assign(&mut fast, &weights);
+                simd::scalar_add_assign(&mut slow, &weights);
+                assert_eq!(fast, slow);
+
+                simd::avx2::sub_assign(&mut fast, &weights);
+                simd::scalar_sub_assign(&mut slow, &weights);
+                assert_eq!(fast, values);
+                assert_eq!(slow, values);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_updates_match_a_refresh() {
+        let mut rng = StdRng::seed_from_u64(0x6e6e7565);
+        let network = Arc::new(random_network(&mut rng, 32));
+        let mut board = Board::new();
+        let mut acc = Accumulator::new(network.clone(), &board);
+        let mut moves = Vec::new();
+
+        for _ in 0..60 {
+            let mv = Square::new(rng.gen_range(0..BOARD_SIZE as u16));
+
+            if board.stone_at(mv) != Stone::Empty {
+                continue;
+            }
+
+            acc.add_stone(mv, board.turn());
+            board.push(mv);
+            moves.push(mv);
+        }
+
+        while let Some(mv) = moves.pop() {
+            let refresh = Accumulator::new(network.clone(), &board);
+
+            assert_eq!(acc.values, refresh.values);
+            assert_eq!(acc.evaluate(board.turn()), refresh.evaluate(board.turn()));
+
+            board.pop(mv);
+            acc.remove_stone(mv, board.turn());
+        }
+
+        assert_eq!(
+            acc.values,
+            [network.hidden_biases.clone(), network.hidden_biases.clone()]
+        );
+    }
+}
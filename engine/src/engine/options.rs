@@ -0,0 +1,395 @@
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+use super::book::{Book, BOOK};
+use super::eval::{EvalParams, EVAL_NETWORK, EVAL_PARAMS};
+use super::nnue::Network;
+use super::search::PRUNING_PARAMS;
+use super::tt::{TranspositionTable, TT};
+
+lazy_static! {
+    pub static ref OPTIONS: RwLock<Options> = RwLock::new(Options::new());
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionKind {
+    Check {
+        default: bool,
+    },
+    Spin {
+        default: i64,
+        min: i64,
+        max: i64,
+    },
+    Combo {
+        default: &'static str,
+        vars: &'static [&'static str],
+    },
+    Button,
+    String {
+        default: &'static str,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionValue {
+    Check(bool),
+    Spin(i64),
+    Combo(String),
+    Button,
+    String(String),
+}
+
+// Called with the option name and its new value once it has been validated, to
+// apply it to the engine state that cannot read the registry on the fly.
+type Handler = fn(&str, &OptionValue) -> io::Result<()>;
+
+pub struct EngineOption {
+    name: String,
+    kind: OptionKind,
+    value: OptionValue,
+    handler: Option<Handler>,
+}
+
+impl EngineOption {
+    fn new(name: &str, kind: OptionKind, handler: Option<Handler>) -> Self {
+        let value = match kind {
+            OptionKind::Check { default } => OptionValue::Check(default),
+            OptionKind::Spin { default, .. } => OptionValue::Spin(default),
+            OptionKind::Combo { default, .. } => OptionValue::Combo(default.to_string()),
+            OptionKind::Button => OptionValue::Button,
+            OptionKind::String { default } => OptionValue::String(default.to_string()),
+        };
+
+        Self {
+            name: name.to_string(),
+            kind,
+            value,
+            handler,
+        }
+    }
+
+    fn parse(&self, value: Option<&str>) -> io::Result<OptionValue> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid value");
+
+        match (&self.kind, value) {
+            (OptionKind::Button, _) => Ok(OptionValue::Button),
+            (_, None) => Err(io::Error::new(io::ErrorKind::InvalidInput, "missing value")),
+            (OptionKind::Check { .. }, Some(value)) => match value {
+                _ if value.eq_ignore_ascii_case("true") => Ok(OptionValue::Check(true)),
+                _ if value.eq_ignore_ascii_case("false") => Ok(OptionValue::Check(false)),
+                _ => Err(invalid()),
+            },
+            (OptionKind::Spin { min, max, .. }, Some(value)) => match value.parse::<i64>() {
+                Ok(v) if (*min..=*max).contains(&v) => Ok(OptionValue::Spin(v)),
+                Ok(_) => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("value out of range [{}, {}]", min, max),
+                )),
+                Err(_) => Err(invalid()),
+            },
+            (OptionKind::Combo { vars, .. }, Some(value)) => vars
+                .iter()
+                .find(|var| var.eq_ignore_ascii_case(value))
+                .map(|var| OptionValue::Combo(var.to_string()))
+                .ok_or_else(invalid),
+            (OptionKind::String { .. }, Some(value)) => Ok(OptionValue::String(match value {
+                "<empty>" => String::new(),
+                _ => value.to_string(),
+            })),
+        }
+    }
+}
+
+impl fmt::Display for EngineOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+
+        match &self.kind {
+            OptionKind::Check { default } => write!(f, "check default {}", default),
+            OptionKind::Spin { default, min, max } => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            OptionKind::Combo { default, vars } => {
+                write!(f, "combo default {}", default)?;
+                vars.iter().try_for_each(|var| write!(f, " var {}", var))
+            }
+            OptionKind::Button => write!(f, "button"),
+            OptionKind::String { default } => write!(
+                f,
+                "string default {}",
+                if default.is_empty() {
+                    "<empty>"
+                } else {
+                    default
+                }
+            ),
+        }
+    }
+}
+
+pub struct Options {
+    options: Vec<EngineOption>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        let spin = |default, min, max| OptionKind::Spin { default, min, max };
+        let check = |default| OptionKind::Check { default };
+        let mut options = vec![
+            EngineOption::new(
+                "Hash",
+                spin(TranspositionTable::DEFAULT_MB as i64, 1, 65536),
+                Some(set_hash),
+            ),
+            EngineOption::new("Clear Hash", OptionKind::Button, Some(clear_hash)),
+            EngineOption::new("Threads", spin(1, 1, 1024), None),
+            EngineOption::new("MultiPV", spin(1, 1, 256), None),
+            // Only tells the GUI that the engine supports `go ponder`.
+            EngineOption::new("Ponder", check(false), None),
+            EngineOption::new("CurrMoveDelay", spin(3000, 0, 3_600_000), None),
+            EngineOption::new(
+                "Ruleset",
+                OptionKind::Combo {
+                    default: "freestyle",
+                    vars: &["freestyle"],
+                },
+                None,
+            ),
+            EngineOption::new("OwnBook", check(false), None),
+            EngineOption::new(
+                "BookFile",
+                OptionKind::String { default: "" },
+                Some(set_book_file),
+            ),
+            EngineOption::new("UseNNUE", check(false), None),
+            EngineOption::new(
+                "EvalFile",
+                OptionKind::String { default: "" },
+                Some(set_eval_file),
+            ),
+            EngineOption::new(
+                "WeightsFile",
+                OptionKind::String { default: "" },
+                Some(set_weights_file),
+            ),
+            EngineOption::new("LMRMinDepth", spin(3, 1, 64), Some(set_pruning)),
+            EngineOption::new("LMRMinMoves", spin(3, 1, 361), Some(set_pruning)),
+            EngineOption::new("LMRBase", spin(50, -1000, 1000), Some(set_pruning)),
+            EngineOption::new("LMRDivisor", spin(250, 1, 10000), Some(set_pruning)),
+            EngineOption::new("FutilityDepth", spin(2, 0, 64), Some(set_pruning)),
+            EngineOption::new("FutilityMargin", spin(150, 0, 10000), Some(set_pruning)),
+            EngineOption::new("NullMove", check(false), Some(set_pruning)),
+            EngineOption::new("NullMoveMinDepth", spin(3, 1, 64), Some(set_pruning)),
+            EngineOption::new("NullMoveReduction", spin(2, 1, 64), Some(set_pruning)),
+        ];
+        let weights = EvalParams::new();
+
+        options.extend(
+            EvalParams::names()
+                .zip(weights.values())
+                .map(|(name, value)| {
+                    EngineOption::new(
+                        &name,
+                        spin(
+                            value as i64,
+                            -EvalParams::MAX_WEIGHT as i64,
+                            EvalParams::MAX_WEIGHT as i64,
+                        ),
+                        Some(set_eval_weight),
+                    )
+                }),
+        );
+
+        Self { options }
+    }
+
+    // Option names are case-insensitive, as in UCI.
+    pub fn get(&self, name: &str) -> Option<&OptionValue> {
+        self.options
+            .iter()
+            .find(|option| option.name.eq_ignore_ascii_case(name))
+            .map(|option| &option.value)
+    }
+
+    pub fn check(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            OptionValue::Check(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn spin(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            OptionValue::Spin(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            OptionValue::Combo(value) | OptionValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Validates the value against the option type, applies it and only then
+    // records it, so that a failing handler leaves the option unchanged.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> io::Result<()> {
+        let option = self
+            .options
+            .iter_mut()
+            .find(|option| option.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown option"))?;
+        let value = option.parse(value)?;
+
+        if let Some(handler) = option.handler {
+            handler(&option.name, &value)?;
+        }
+
+        option.value = value;
+        Ok(())
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Options {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.options
+            .iter()
+            .try_for_each(|option| writeln!(f, "{}", option))
+    }
+}
+
+fn set_hash(_: &str, value: &OptionValue) -> io::Result<()> {
+    if let OptionValue::Spin(megabytes) = value {
+        *TT.write().unwrap() = Arc::new(TranspositionTable::try_new(*megabytes as usize)?);
+    }
+
+    Ok(())
+}
+
+fn clear_hash(_: &str, _: &OptionValue) -> io::Result<()> {
+    TT.read().unwrap().clear();
+    Ok(())
+}
+
+fn set_eval_file(_: &str, value: &OptionValue) -> io::Result<()> {
+    let network = match value {
+        OptionValue::String(path) if !path.is_empty() => Some(Arc::new(Network::load(path)?)),
+        _ => None,
+    };
+
+    *EVAL_NETWORK.write().unwrap() = network;
+    Ok(())
+}
+
+fn set_book_file(_: &str, value: &OptionValue) -> io::Result<()> {
+    let book = match value {
+        OptionValue::String(path) if !path.is_empty() => Some(Arc::new(Book::load(path)?)),
+        _ => None,
+    };
+
+    *BOOK.write().unwrap() = book;
+    Ok(())
+}
+
+fn set_weights_file(_: &str, value: &OptionValue) -> io::Result<()> {
+    let params = match value {
+        OptionValue::String(path) if !path.is_empty() => EvalParams::load(path)?,
+        _ => EvalParams::new(),
+    };
+
+    *EVAL_PARAMS.write().unwrap() = params;
+    Ok(())
+}
+
+fn set_eval_weight(name: &str, value: &OptionValue) -> io::Result<()> {
+    match value {
+        OptionValue::Spin(v) => EVAL_PARAMS.write().unwrap().set(name, *v as i16),
+        _ => Ok(()),
+    }
+}
+
+fn set_pruning(name: &str, value: &OptionValue) -> io::Result<()> {
+    let value = match value {
+        OptionValue::Check(v) => v.to_string(),
+        OptionValue::Spin(v) => v.to_string(),
+        _ => return Ok(()),
+    };
+
+    PRUNING_PARAMS.write().unwrap().set(name, &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only options without handlers are set, so that the global engine state
+    // is left untouched.
+    #[test]
+    fn parses_values() {
+        let mut options = Options::new();
+
+        options.set("MultiPV", Some("4")).unwrap();
+        assert_eq!(options.spin("MultiPV"), Some(4));
+
+        options.set("Ponder", Some("true")).unwrap();
+        assert_eq!(options.check("Ponder"), Some(true));
+        options.set("Ponder", Some("FALSE")).unwrap();
+        assert_eq!(options.check("Ponder"), Some(false));
+
+        options.set("Ruleset", Some("Freestyle")).unwrap();
+        assert_eq!(options.string("Ruleset"), Some("freestyle"));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut options = Options::new();
+
+        for (name, value) in [
+            ("MultiPV", Some("0")),
+            ("MultiPV", Some("257")),
+            ("MultiPV", Some("two")),
+            ("MultiPV", None),
+            ("Ponder", Some("yes")),
+            ("Ruleset", Some("renju")),
+            ("NoSuchOption", Some("1")),
+        ] {
+            assert!(options.set(name, value).is_err(), "{} {:?}", name, value);
+        }
+
+        assert_eq!(options.spin("MultiPV"), Some(1));
+        assert_eq!(options.check("Ponder"), Some(false));
+        assert_eq!(options.string("Ruleset"), Some("freestyle"));
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        let mut options = Options::new();
+
+        options.set("multipv", Some("3")).unwrap();
+        assert_eq!(options.spin("MULTIPV"), Some(3));
+        assert_eq!(options.spin("MultiPV"), Some(3));
+        assert_eq!(options.get("clear hash"), Some(&OptionValue::Button));
+    }
+
+    #[test]
+    fn lists_options() {
+        let options = Options::new().to_string();
+
+        assert!(options.contains("option name MultiPV type spin default 1 min 1 max 256\n"));
+        assert!(options.contains("option name Ponder type check default false\n"));
+        assert!(
+            options.contains("option name Ruleset type combo default freestyle var freestyle\n")
+        );
+        assert!(options.contains("option name BookFile type string default <empty>\n"));
+        assert!(options.contains("option name Clear Hash type button\n"));
+    }
+}
@@ -29,8 +29,8 @@ impl Display for ScoreKind {
                 };
                 write!(f, "{}{}.{:02}", sign, cp.abs() / 100, cp.abs() % 100)
             }
-            ScoreKind::MateIn(m) => write!(f, "+M{}", (m + 1) / 2),
-            ScoreKind::MatedIn(m) => write!(f, "-M{}", (m + 1) / 2),
+            ScoreKind::MateIn(m) => write!(f, "+M{}", m.div_ceil(2)),
+            ScoreKind::MatedIn(m) => write!(f, "-M{}", m.div_ceil(2)),
         }
     }
 }
@@ -60,6 +60,10 @@ impl Score {
         Self(-Self::mate_in(plies_to_mate).0)
     }
 
+    pub const fn value(self) -> i16 {
+        self.0
+    }
+
     pub const fn kind(self) -> ScoreKind {
         match self.0 {
             v if v >= Self::MAX_MATE_IN.0 => ScoreKind::MateIn((Self::MIN_MATE_IN.0 - v) as u8),
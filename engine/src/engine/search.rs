@@ -1,17 +1,119 @@
-use crate::core::{board::Board, types::Square};
+use crate::core::{
+    bitboard::BitboardIter,
+    board::Board,
+    magic,
+    types::{Square, Stone},
+};
 use std::io::{self, Write};
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, RwLock,
 };
+use std::thread;
 use std::time::{Duration, Instant};
 
-use super::eval;
-use super::movegen::Movegen;
+use super::eval::{self, EvalParams, EVAL_PARAMS};
+use super::history::History;
+use super::movegen::{Movegen, Tactic};
+use super::nnue::{Accumulator, Network};
+use super::options::OPTIONS;
 use super::score::{Score, ScoreKind};
+use super::tt::{Bound, TranspositionTable, TT};
+
+const ASPIRATION_WINDOW: Score = Score::cp(25);
+const ASPIRATION_LIMIT: i16 = 10000;
+const QUIESCENCE_MAX_PLY: u16 = 16;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Helper threads skip iterations in blocks of `SKIP_SIZE` depths, starting at
+// `SKIP_PHASE`, so that they spread over depths instead of duplicating the
+// main thread. The patterns repeat every 20 helpers.
+const SKIP_SIZE: [u16; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u16; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
 
 lazy_static! {
     pub static ref STOP: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
+    pub static ref PONDER: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    pub static ref PRUNING_PARAMS: RwLock<PruningParams> = RwLock::new(PruningParams::new());
+}
+
+// Selectivity parameters. LMR reduces late non-forcing moves by
+// `(lmr_base + ln(depth) * ln(move_idx) * 10000 / lmr_divisor) / 100` plies,
+// one less for threes and blocks of threes, futility pruning skips
+// non-forcing moves when the static evaluation plus `futility_margin` per ply
+// of depth cannot reach alpha, and null move pruning (off by default) tries
+// passing before searching.
+#[derive(Clone, Debug)]
+pub struct PruningParams {
+    lmr_min_depth: u16,
+    lmr_min_moves: usize,
+    lmr_base: i32,
+    lmr_divisor: i32,
+    futility_depth: u16,
+    futility_margin: i16,
+    null_move: bool,
+    null_move_min_depth: u16,
+    null_move_reduction: u16,
+}
+
+impl PruningParams {
+    pub fn new() -> Self {
+        Self {
+            lmr_min_depth: 3,
+            lmr_min_moves: 3,
+            lmr_base: 50,
+            lmr_divisor: 250,
+            futility_depth: 2,
+            futility_margin: 150,
+            null_move: false,
+            null_move_min_depth: 3,
+            null_move_reduction: 2,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> io::Result<()> {
+        fn parse<T: std::str::FromStr>(value: &str) -> io::Result<T> {
+            value
+                .parse()
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+        }
+
+        match name {
+            "LMRMinDepth" => self.lmr_min_depth = parse(value)?,
+            "LMRMinMoves" => self.lmr_min_moves = parse(value)?,
+            "LMRBase" => self.lmr_base = parse(value)?,
+            "LMRDivisor" => self.lmr_divisor = parse::<i32>(value)?.max(1),
+            "FutilityDepth" => self.futility_depth = parse(value)?,
+            "FutilityMargin" => self.futility_margin = parse(value)?,
+            "NullMove" => self.null_move = parse(value)?,
+            "NullMoveMinDepth" => self.null_move_min_depth = parse(value)?,
+            "NullMoveReduction" => self.null_move_reduction = parse(value)?,
+            _ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        }
+
+        Ok(())
+    }
+
+    // Moves making a three or blocking one of the opponent, with a positive
+    // pattern strength, are reduced one ply less than quiet moves.
+    fn reduction(&self, depth: u16, move_idx: usize, pattern: i32) -> u16 {
+        if depth < self.lmr_min_depth || move_idx < self.lmr_min_moves {
+            return 0;
+        }
+
+        let r = (self.lmr_base as f64
+            + (depth as f64).ln() * (move_idx as f64).ln() * 10000.0 / self.lmr_divisor as f64)
+            / 100.0;
+        let r = (r.max(0.0) as u16).saturating_sub((pattern > 0) as u16);
+
+        r.min(depth - 1)
+    }
+}
+
+impl Default for PruningParams {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct Params {
@@ -88,6 +190,28 @@ impl Params {
     pub fn movetime(&self) -> Duration {
         self.movetime.unwrap_or(Duration::MAX)
     }
+
+    // Caps the search time with a share of the remaining time of the side to
+    // move, keeping a safety margin for communication overhead.
+    pub fn allocate_time(&mut self, turn: Stone) {
+        let (time, inc) = match turn {
+            Stone::White => (self.wtime, self.winc),
+            _ => (self.btime, self.binc),
+        };
+
+        if let Some(time) = time {
+            let margin = Duration::from_millis(50).min(time / 2);
+            let budget = (time / 30 + inc.unwrap_or_default() * 3 / 4).min(time - margin);
+
+            self.movetime = Some(self.movetime().min(budget));
+        }
+    }
+
+    fn is_searchmove(&self, mv: Square) -> bool {
+        self.searchmoves
+            .as_ref()
+            .is_none_or(|moves| moves.contains(&mv))
+    }
 }
 
 impl Default for Params {
@@ -98,22 +222,95 @@ impl Default for Params {
 
 pub struct SearchData {
     start: Instant,
+    clock_start: Instant,
     node_count: u64,
+    flushed_nodes: u64,
+    total_nodes: Arc<AtomicU64>,
     iter_depth: u16,
+    completed_depth: u16,
     seldepth: u16,
     check_in: u16,
     bestmove: Square,
+    stop: Arc<AtomicBool>,
+    tt: Arc<TranspositionTable>,
+    eval_params: EvalParams,
+    network: Option<Arc<Network>>,
+    accumulator: Option<Accumulator>,
+    played: Vec<Square>,
+    history: History,
+    pruning: PruningParams,
+    in_null_move: bool,
+    multipv: usize,
+    currmove_delay: Duration,
+    multipv_idx: usize,
+    excluded: Vec<Square>,
+    progress: bool,
+    last_heartbeat: Instant,
+    thread_idx: usize,
 }
 
 impl SearchData {
     pub fn new() -> Self {
+        Self::with_stop(STOP.clone())
+    }
+
+    pub fn with_stop(stop: Arc<AtomicBool>) -> Self {
+        Self::for_thread(0, stop, Arc::new(AtomicU64::new(0)))
+    }
+
+    // Creates search data ignoring the options and the loaded parameters and
+    // network, for searches that must not depend on them.
+    pub fn with_defaults(stop: Arc<AtomicBool>) -> Self {
+        Self {
+            eval_params: EvalParams::new(),
+            network: None,
+            pruning: PruningParams::new(),
+            multipv: 1,
+            ..Self::with_stop(stop)
+        }
+    }
+
+    // Creates the search data of one of the threads of a search, all threads
+    // sharing the stop flag, the node counter and the transposition table.
+    pub fn for_thread(
+        thread_idx: usize,
+        stop: Arc<AtomicBool>,
+        total_nodes: Arc<AtomicU64>,
+    ) -> Self {
+        let options = OPTIONS.read().unwrap();
+
         Self {
             start: Instant::now(),
+            clock_start: Instant::now(),
             node_count: 0,
+            flushed_nodes: 0,
+            total_nodes,
             iter_depth: 0,
+            completed_depth: 0,
             seldepth: 0,
             check_in: 0,
             bestmove: Square::new(0),
+            stop,
+            tt: TT.read().unwrap().clone(),
+            eval_params: EVAL_PARAMS.read().unwrap().clone(),
+            network: eval::active_network(),
+            accumulator: None,
+            played: Vec::new(),
+            history: History::new(),
+            pruning: PRUNING_PARAMS.read().unwrap().clone(),
+            in_null_move: false,
+            // Helper threads only help finding the best move.
+            multipv: if thread_idx == 0 {
+                options.spin("MultiPV").unwrap_or(1) as usize
+            } else {
+                1
+            },
+            currmove_delay: Duration::from_millis(options.spin("CurrMoveDelay").unwrap_or(0) as u64),
+            multipv_idx: 0,
+            excluded: Vec::new(),
+            progress: false,
+            last_heartbeat: Instant::now(),
+            thread_idx,
         }
     }
 
@@ -122,13 +319,67 @@ impl SearchData {
             self.check_in -= 1;
         } else {
             self.check_in = 256;
+            self.flush_nodes();
+
+            if self.progress && self.last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+                self.last_heartbeat = Instant::now();
+                self.report_heartbeat();
+            }
+
+            // Limits only apply once pondering is over, the time budget
+            // starting on `ponderhit`.
+            if PONDER.load(Ordering::Relaxed) {
+                self.clock_start = Instant::now();
+                return;
+            }
 
-            if self.node_count >= params.nodes() || self.elapsed() >= params.movetime() {
-                STOP.store(true, Ordering::Relaxed);
+            if self.total_nodes() >= params.nodes()
+                || Instant::now() - self.clock_start >= params.movetime()
+            {
+                self.stop.store(true, Ordering::Relaxed);
             }
         }
     }
 
+    // Makes the search send `info` lines on its progress during iterations.
+    pub fn enable_progress(&mut self) {
+        self.progress = true;
+    }
+
+    fn report_heartbeat(&self) {
+        let elapsed = self.elapsed();
+        let nodes = self.total_nodes();
+
+        println!(
+            "info nodes {} nps {} time {} hashfull {}",
+            nodes,
+            (nodes as f64 / elapsed.as_secs_f64()).round() as u64,
+            elapsed.as_millis(),
+            self.tt().hashfull(),
+        );
+        let _ = io::stdout().flush();
+    }
+
+    fn report_currmove(&self, mv: Square, move_number: usize) {
+        if self.progress && self.elapsed() >= self.currmove_delay {
+            println!(
+                "info depth {} currmove {} currmovenumber {}",
+                self.iter_depth, mv, move_number
+            );
+            let _ = io::stdout().flush();
+        }
+    }
+
+    fn flush_nodes(&mut self) {
+        self.total_nodes
+            .fetch_add(self.node_count - self.flushed_nodes, Ordering::Relaxed);
+        self.flushed_nodes = self.node_count;
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
     pub fn elapsed(&self) -> Duration {
         Instant::now() - self.start
     }
@@ -137,6 +388,12 @@ impl SearchData {
         self.node_count
     }
 
+    // Nodes searched by all threads, counting the nodes of other threads since
+    // their last time check.
+    pub fn total_nodes(&self) -> u64 {
+        self.total_nodes.load(Ordering::Relaxed) + self.node_count - self.flushed_nodes
+    }
+
     pub fn inc_node_count(&mut self) {
         self.node_count += 1;
     }
@@ -149,6 +406,19 @@ impl SearchData {
         self.iter_depth += 1;
     }
 
+    pub fn completed_depth(&self) -> u16 {
+        self.completed_depth
+    }
+
+    // Rank of the root move being searched, starting from 1.
+    pub fn multipv_idx(&self) -> usize {
+        self.multipv_idx + 1
+    }
+
+    pub fn multipv(&self) -> usize {
+        self.multipv
+    }
+
     pub fn seldepth(&self) -> u16 {
         self.seldepth
     }
@@ -164,6 +434,51 @@ impl SearchData {
     pub fn set_bestmove(&mut self, mv: Square) {
         self.bestmove = mv;
     }
+
+    pub fn tt(&self) -> &TranspositionTable {
+        &self.tt
+    }
+
+    // Evaluates with the weights captured when the search started, so that
+    // threads do not contend on the global weights lock.
+    pub fn evaluate(&self, board: &Board) -> Score {
+        match self.accumulator.as_ref() {
+            Some(acc) => acc.evaluate(board.turn()),
+            None => eval::evaluate_with(board, &self.eval_params),
+        }
+    }
+
+    pub fn set_tt(&mut self, tt: Arc<TranspositionTable>) {
+        self.tt = tt;
+    }
+
+    fn skips_depth(&self, depth: u16) -> bool {
+        let Some(idx) = self.thread_idx.checked_sub(1) else {
+            return false;
+        };
+
+        (depth + SKIP_PHASE[idx % 20]) / SKIP_SIZE[idx % 20] % 2 == 1
+    }
+
+    // Plays the move, keeping the network accumulator in sync with the board
+    // and recording the moves played since the root.
+    fn play(&mut self, board: &mut Board, mv: Square) {
+        if let Some(acc) = self.accumulator.as_mut() {
+            acc.add_stone(mv, board.turn());
+        }
+
+        board.push(mv);
+        self.played.push(mv);
+    }
+
+    fn unplay(&mut self, board: &mut Board, mv: Square) {
+        board.pop(mv);
+        self.played.pop();
+
+        if let Some(acc) = self.accumulator.as_mut() {
+            acc.remove_stone(mv, board.turn());
+        }
+    }
 }
 
 impl Default for SearchData {
@@ -173,53 +488,259 @@ impl Default for SearchData {
 }
 
 pub fn run_search(board: &Board, params: &Params) -> io::Result<()> {
-    let mut data = SearchData::new();
+    let total_nodes = Arc::new(AtomicU64::new(0));
+
+    TT.read().unwrap().new_search();
+
+    let bestmove = thread::scope(|scope| -> io::Result<Square> {
+        let threads = OPTIONS.read().unwrap().spin("Threads").unwrap_or(1) as usize;
+        let helpers = (1..threads)
+            .map(|thread_idx| {
+                let total_nodes = total_nodes.clone();
+
+                scope.spawn(move || {
+                    let mut data = SearchData::for_thread(thread_idx, STOP.clone(), total_nodes);
+                    let _ = iterative_search(board, params, &mut data, |_, _, _| Ok(()));
+
+                    (data.completed_depth(), data.bestmove())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut data = SearchData::for_thread(0, STOP.clone(), total_nodes.clone());
+
+        data.enable_progress();
+
+        let result = iterative_search(board, params, &mut data, |data, score, bound| {
+            let score_str = match score.kind() {
+                ScoreKind::Centipoint(v) => format!("cp {}", v),
+                ScoreKind::MateIn(v) => format!("mate {}", v.div_ceil(2)),
+                ScoreKind::MatedIn(v) => format!("mate -{}", v.div_ceil(2)),
+            } + match bound {
+                Bound::Exact => "",
+                Bound::Lower => " lowerbound",
+                Bound::Upper => " upperbound",
+            };
+            let elapsed = data.elapsed();
+            let nodes = data.total_nodes();
+
+            let multipv_str = match data.multipv() {
+                1 => String::new(),
+                _ => format!(" multipv {}", data.multipv_idx()),
+            };
+
+            println!(
+                "info depth {} seldepth {}{} score {} nodes {} nps {} time {} hashfull {} pv {}",
+                data.iter_depth(),
+                data.seldepth(),
+                multipv_str,
+                score_str,
+                nodes,
+                (nodes as f64 / elapsed.as_secs_f64()).round() as u64,
+                elapsed.as_millis(),
+                data.tt().hashfull(),
+                data.bestmove(),
+            );
+            io::stdout().flush()
+        });
+
+        // The best move cannot be sent before the predicted move is played or
+        // the search is stopped.
+        while PONDER.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        STOP.store(true, Ordering::Relaxed);
+
+        // Play the move of the thread having completed the deepest iteration,
+        // preferring the main thread on ties.
+        let mut best = (data.completed_depth(), data.bestmove());
+
+        for helper in helpers {
+            let (depth, mv) = helper.join().unwrap();
+
+            if depth > best.0 {
+                best = (depth, mv);
+            }
+        }
+
+        result.map(|_| best.1)
+    })?;
+
+    match ponder_move(board, bestmove) {
+        Some(ponder) => println!("bestmove {} ponder {}", bestmove, ponder),
+        None => println!("bestmove {}", bestmove),
+    }
+
+    io::stdout().flush()
+}
+
+// Returns the expected reply to the best move, read from the transposition
+// table, if the game is not over after it.
+fn ponder_move(board: &Board, bestmove: Square) -> Option<Square> {
+    let mut board = board.clone();
+
+    if board.stone_at(bestmove) != Stone::Empty {
+        return None;
+    }
+
+    board.push(bestmove);
+
+    if eval::is_victory(&board, bestmove) {
+        return None;
+    }
+
+    TT.read()
+        .unwrap()
+        .probe(board.key())
+        .and_then(|entry| entry.bestmove())
+        .filter(|&sq| board.stone_at(sq) == Stone::Empty)
+}
+
+// Runs iterative deepening on the position, calling `report` after each
+// completed iteration and after each aspiration window failure (with the
+// corresponding bound), and returns the score of the last completed iteration.
+// Helper threads skip every other depth on odd thread indices to
+// desynchronize their trees. With several principal variations, each
+// iteration searches the root again for each of them, excluding the moves
+// already found, and the best move and score are those of the first one.
+pub fn iterative_search<F>(
+    board: &Board,
+    params: &Params,
+    data: &mut SearchData,
+    mut report: F,
+) -> io::Result<Score>
+where
+    F: FnMut(&SearchData, Score, Bound) -> io::Result<()>,
+{
     let mut board = board.clone();
+    let mut last_score = Score::ZERO;
+    let mut last_scores: Vec<Score> = Vec::new();
 
-    STOP.store(false, Ordering::Relaxed);
+    data.accumulator = data
+        .network
+        .clone()
+        .map(|network| Accumulator::new(network, &board));
+    data.set_bestmove(fallback_move(&board, data, params));
 
     while data.iter_depth() < params.depth() {
         data.inc_iter_depth();
         data.set_seldepth(0);
         let iter_depth = data.iter_depth();
 
-        let score = search(
-            &mut board,
-            &mut data,
-            params,
-            iter_depth,
-            Score::MIN,
-            Score::MAX,
-            0,
-        );
+        if data.skips_depth(iter_depth) && iter_depth < params.depth() {
+            continue;
+        }
 
-        let score_str = match score.kind() {
-            ScoreKind::Centipoint(v) => format!("cp {}", v),
-            ScoreKind::MateIn(v) => format!("mate {}", (v + 1) / 2),
-            ScoreKind::MatedIn(v) => format!("mate -{}", (v + 1) / 2),
-        };
+        let mut first_move = None;
 
-        if STOP.load(Ordering::Relaxed) {
-            break;
+        data.excluded.clear();
+
+        for pv_idx in 0..data.multipv {
+            data.multipv_idx = pv_idx;
+
+            let mut delta = ASPIRATION_WINDOW;
+            let (mut alpha, mut beta) = match last_scores.get(pv_idx).copied() {
+                Some(last)
+                    if iter_depth >= 4 && matches!(last.kind(), ScoreKind::Centipoint(_)) =>
+                {
+                    (last.saturating_sub(delta), last.saturating_add(delta))
+                }
+                _ => (Score::MIN, Score::MAX),
+            };
+
+            loop {
+                let score = search(&mut board, data, params, iter_depth, alpha, beta, 0);
+
+                if data.stopped() {
+                    break;
+                }
+
+                if score <= alpha && alpha != Score::MIN {
+                    report(data, score, Bound::Upper)?;
+                    beta = Score::cp(((alpha.value() as i32 + beta.value() as i32) / 2) as i16);
+                    alpha = widen(score.saturating_sub(delta), Score::MIN);
+                } else if score >= beta && beta != Score::MAX {
+                    report(data, score, Bound::Lower)?;
+                    beta = widen(score.saturating_add(delta), Score::MAX);
+                } else {
+                    // No root move was left to search.
+                    if score == Score::MIN {
+                        break;
+                    }
+
+                    if pv_idx == 0 {
+                        last_score = score;
+                        data.completed_depth = iter_depth;
+                        first_move = Some(data.bestmove());
+                    }
+
+                    last_scores.truncate(pv_idx);
+                    last_scores.push(score);
+                    data.excluded.push(data.bestmove());
+                    report(data, score, Bound::Exact)?;
+                    break;
+                }
+
+                delta = delta.saturating_mul(2);
+            }
+
+            if data.stopped() || data.excluded.len() <= pv_idx {
+                break;
+            }
         }
 
-        let elapsed = data.elapsed();
+        data.excluded.clear();
+        data.multipv_idx = 0;
 
-        println!(
-            "info depth {} seldepth {} score {} nodes {} nps {} time {} pv {}",
-            data.iter_depth(),
-            data.seldepth(),
-            score_str,
-            data.node_count(),
-            (data.node_count() as f64 / elapsed.as_secs_f64()).round() as u64,
-            elapsed.as_millis(),
-            data.bestmove(),
-        );
-        io::stdout().flush()?;
+        if let Some(mv) = first_move {
+            data.set_bestmove(mv);
+        }
+
+        if data.stopped() {
+            break;
+        }
     }
 
-    println!("bestmove {}", data.bestmove());
-    io::stdout().flush()
+    data.flush_nodes();
+    Ok(last_score)
+}
+
+// Returns the move played if the search stops before completing an iteration:
+// the immediate tactic, the move of the transposition table or the first legal
+// move, in this order, preferring the moves allowed by `searchmoves`.
+fn fallback_move(board: &Board, data: &SearchData, params: &Params) -> Square {
+    let tactic = Tactic::detect(board).map(|tactic| tactic.square());
+    let tt_move = data
+        .tt()
+        .probe(board.key())
+        .and_then(|entry| entry.bestmove());
+    let mut movegen = Movegen::new();
+
+    movegen.generate_near(board);
+    movegen.generate_all(board);
+
+    let legal = tactic
+        .into_iter()
+        .chain(tt_move)
+        .chain(movegen)
+        .filter(|&mv| mv.is_valid() && board.stone_at(mv) == Stone::Empty)
+        .collect::<Vec<_>>();
+
+    legal
+        .iter()
+        .find(|&&mv| params.is_searchmove(mv))
+        .or(legal.first())
+        .copied()
+        .unwrap_or(Square::new(0))
+}
+
+// Opens a window bound fully once it reaches the mate score range.
+fn widen(bound: Score, limit: Score) -> Score {
+    match bound.kind() {
+        ScoreKind::Centipoint(v) if v.abs() < ASPIRATION_LIMIT => bound,
+        _ => limit,
+    }
 }
 
 pub fn search(
@@ -233,47 +754,213 @@ pub fn search(
 ) -> Score {
     data.check_time(params);
 
-    if STOP.load(Ordering::Relaxed) {
+    if data.stopped() {
         return Score::cp(0);
     }
 
     data.set_seldepth(ply);
 
     if depth == 0 {
-        return eval::evaluate(board);
+        return quiescence(board, data, params, alpha, beta, ply, 0);
+    }
+
+    let restricted = ply == 0 && (!data.excluded.is_empty() || params.searchmoves.is_some());
+    let tactic = if restricted {
+        None
+    } else {
+        Tactic::detect(board)
+    };
+
+    match tactic {
+        Some(Tactic::Win(mv)) => {
+            data.inc_node_count();
+
+            if ply == 0 {
+                data.set_bestmove(mv);
+            }
+
+            return Score::mate_in(ply as u8 + 1);
+        }
+        Some(Tactic::Lost(_)) if ply > 0 => return Score::mated_in(ply as u8 + 2),
+        _ => (),
+    }
+
+    let key = board.key();
+    let tt_entry = data.tt().probe(key);
+
+    if let Some(entry) = tt_entry {
+        let score = entry.score(ply);
+
+        if ply > 0
+            && entry.depth() >= depth
+            && match entry.bound() {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            }
+        {
+            return score;
+        }
+    }
+
+    let pv_node = beta.value() as i32 - alpha.value() as i32 > 1;
+    let static_eval = (ply > 0
+        && !pv_node
+        && tactic.is_none()
+        && (depth <= data.pruning.futility_depth || data.pruning.null_move))
+        .then(|| data.evaluate(board));
+
+    if let Some(eval) = static_eval {
+        if data.pruning.null_move
+            && !data.in_null_move
+            && depth >= data.pruning.null_move_min_depth
+            && eval >= beta
+        {
+            let r = data.pruning.null_move_reduction.min(depth - 1);
+
+            board.push_null();
+            data.in_null_move = true;
+
+            let score = -search(
+                board,
+                data,
+                params,
+                depth - 1 - r,
+                -beta,
+                -beta + Score::UNIT,
+                ply + 1,
+            );
+
+            data.in_null_move = false;
+            board.pop_null();
+
+            if data.stopped() {
+                return Score::cp(0);
+            }
+
+            if score >= beta {
+                return match score.kind() {
+                    ScoreKind::Centipoint(_) => score,
+                    _ => beta,
+                };
+            }
+        }
     }
 
+    let futility_limit = static_eval
+        .filter(|_| depth <= data.pruning.futility_depth)
+        .map(|eval| {
+            eval.saturating_add(
+                Score::cp(data.pruning.futility_margin).saturating_mul(depth as i16),
+            )
+        });
+    let old_alpha = alpha;
     let mut movegen = Movegen::new();
 
-    if depth >= 5 {
-        movegen.generate_far(board);
+    if let Some(tactic) = tactic {
+        movegen.restrict_to(tactic.square());
     } else {
-        movegen.generate_near(board);
-    }
+        if depth >= 5 {
+            movegen.generate_far(board);
+        } else {
+            movegen.generate_near(board);
+        }
 
-    movegen.order_moves(board);
+        movegen.order_moves_with(board, Some(&data.history), ply);
+
+        if let Some(mv) = tt_entry.and_then(|entry| entry.bestmove()) {
+            movegen.prioritize(mv);
+        }
+    }
 
     let mut bestscore = Score::MIN;
+    let mut bestmove = None;
+    let mut tried = Vec::new();
 
-    for mv in movegen {
-        board.push(mv);
+    let mut move_idx = 0;
+
+    while let Some(mv) = movegen.next() {
+        let forcing = movegen.last_is_forcing();
+
+        if ply == 0 {
+            if data.excluded.contains(&mv) || !params.is_searchmove(mv) {
+                continue;
+            }
+
+            data.report_currmove(mv, move_idx + 1);
+        }
+
+        if move_idx > 0 && !forcing && futility_limit.is_some_and(|limit| limit <= alpha) {
+            continue;
+        }
+
+        data.play(board, mv);
         data.inc_node_count();
 
         if eval::is_victory(board, mv) {
-            board.pop(mv);
-            return Score::mate_in(ply as u8 + 1);
+            data.unplay(board, mv);
+
+            if ply == 0 {
+                data.set_bestmove(mv);
+            }
+
+            let score = Score::mate_in(ply as u8 + 1);
+
+            data.tt()
+                .store(key, Some(mv), score, depth, Bound::Exact, ply);
+            return score;
         }
 
-        let score = -search(board, data, params, depth - 1, -beta, -alpha, ply + 1);
+        // Principal variation search: moves after the first one are searched
+        // with a null window, reduced for late non-forcing moves, and only
+        // re-searched if they beat alpha.
+        let score = if move_idx == 0 {
+            -search(board, data, params, depth - 1, -beta, -alpha, ply + 1)
+        } else {
+            let r = if forcing {
+                0
+            } else {
+                data.pruning
+                    .reduction(depth, move_idx, movegen.last_pattern())
+            };
+            let mut score = -search(
+                board,
+                data,
+                params,
+                depth - 1 - r,
+                -alpha - Score::UNIT,
+                -alpha,
+                ply + 1,
+            );
 
-        board.pop(mv);
+            if r > 0 && score > alpha {
+                score = -search(
+                    board,
+                    data,
+                    params,
+                    depth - 1,
+                    -alpha - Score::UNIT,
+                    -alpha,
+                    ply + 1,
+                );
+            }
+
+            if score > alpha && score < beta {
+                -search(board, data, params, depth - 1, -beta, -alpha, ply + 1)
+            } else {
+                score
+            }
+        };
+
+        data.unplay(board, mv);
 
-        if STOP.load(Ordering::Relaxed) {
+        if data.stopped() {
             return Score::cp(0);
         }
 
         if score > bestscore {
             bestscore = score;
+            bestmove = Some(mv);
 
             if score > alpha {
                 alpha = score;
@@ -282,12 +969,277 @@ pub fn search(
                     data.set_bestmove(mv);
                 }
 
-                if score > beta {
+                if score >= beta {
+                    data.history.update(board.turn(), ply, mv, &tried, depth);
                     break;
                 }
             }
         }
+
+        tried.push(mv);
+        move_idx += 1;
+    }
+
+    if bestmove.is_some() && !restricted {
+        let bound = if bestscore >= beta {
+            Bound::Lower
+        } else if bestscore > old_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+
+        data.tt().store(key, bestmove, bestscore, depth, bound, ply);
+    }
+
+    bestscore
+}
+
+// Extends leaves through forcing moves so that the static evaluation is only
+// applied to quiet positions: fives are played, single five-threats of the
+// opponent are blocked, and fours are tried on top of standing pat. Two
+// opponent five-threats cannot both be blocked, so the position is lost.
+// Past the first ply, only fours aligned with the previous move of the side to
+// move are tried, following sequences of continuous fours, and blocks count
+// towards the extension limit like fours.
+fn quiescence(
+    board: &mut Board,
+    data: &mut SearchData,
+    params: &Params,
+    mut alpha: Score,
+    beta: Score,
+    ply: u16,
+    qply: u16,
+) -> Score {
+    data.check_time(params);
+
+    if data.stopped() {
+        return Score::cp(0);
+    }
+
+    data.set_seldepth(ply);
+
+    let tactic = Tactic::detect(board);
+
+    match tactic {
+        Some(Tactic::Win(_)) => return Score::mate_in(ply as u8 + 1),
+        Some(Tactic::Lost(_)) => return Score::mated_in(ply as u8 + 2),
+        _ => (),
+    }
+
+    let key = board.key();
+    let tt_entry = data.tt().probe(key);
+
+    if let Some(entry) = tt_entry {
+        let score = entry.score(ply);
+
+        if match entry.bound() {
+            Bound::Exact => true,
+            Bound::Lower => score >= beta,
+            Bound::Upper => score <= alpha,
+        } {
+            return score;
+        }
+    }
+
+    if qply >= QUIESCENCE_MAX_PLY {
+        return data.evaluate(board);
+    }
+
+    let old_alpha = alpha;
+    let mut bestmove = None;
+    let mut bestscore;
+
+    if let Some(Tactic::Block(mv)) = tactic {
+        data.play(board, mv);
+        data.inc_node_count();
+        bestscore = -quiescence(board, data, params, -beta, -alpha, ply + 1, qply + 1);
+        data.unplay(board, mv);
+        bestmove = Some(mv);
+    } else {
+        bestscore = data.evaluate(board);
+
+        if bestscore >= beta {
+            return bestscore;
+        }
+
+        alpha = alpha.max(bestscore);
+
+        let previous = data.played.len().checked_sub(2).map(|idx| data.played[idx]);
+        let mut fours = magic::side_fours(board, board.turn());
+
+        if let Some(previous) = previous.filter(|_| qply > 0) {
+            fours &= magic::line_neighbourhood(previous);
+        }
+
+        for mv in BitboardIter::from_bitboard(&fours) {
+            data.play(board, mv);
+            data.inc_node_count();
+
+            let score = -quiescence(board, data, params, -beta, -alpha, ply + 1, qply + 1);
+
+            data.unplay(board, mv);
+
+            if data.stopped() {
+                return Score::cp(0);
+            }
+
+            if score > bestscore {
+                bestscore = score;
+                bestmove = Some(mv);
+
+                if score > alpha {
+                    alpha = score;
+
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if data.stopped() {
+        return Score::cp(0);
+    }
+
+    // Entries of the main search are more accurate, whatever their bound.
+    if tt_entry.is_none_or(|entry| entry.depth() == 0) {
+        let bound = if bestscore >= beta {
+            Bound::Lower
+        } else if bestscore > old_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+
+        data.tt().store(key, bestmove, bestscore, 0, bound, ply);
     }
 
     bestscore
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_from(moves: &str) -> Board {
+        let mut board = Board::new();
+
+        for mv in moves.split_ascii_whitespace() {
+            board.push(mv.parse().unwrap());
+        }
+
+        board
+    }
+
+    fn new_data() -> SearchData {
+        let mut data = SearchData::with_stop(Arc::new(AtomicBool::new(false)));
+
+        data.set_tt(Arc::new(TranspositionTable::new(1)));
+        data
+    }
+
+    // Black threatens to complete h08-l08 on k08 only: the squares beyond the
+    // four are no wins nor blocks.
+    const BROKEN_FOUR: &str = "h08 a01 i08 a03 j08 a05 l08";
+
+    #[test]
+    fn quiescence_blocks_the_five() {
+        let mut board = board_from(BROKEN_FOUR);
+        let mut data = new_data();
+        let params = Params::new();
+        let score = quiescence(&mut board, &mut data, &params, Score::MIN, Score::MAX, 0, 0);
+
+        assert!(
+            matches!(score.kind(), ScoreKind::Centipoint(_)),
+            "{}",
+            score
+        );
+
+        let mut params = Params::new();
+        let mut data = new_data();
+
+        params.set_depth(1);
+        iterative_search(&board, &params, &mut data, |_, _, _| Ok(())).unwrap();
+        assert_eq!(data.bestmove().to_string(), "k08");
+    }
+
+    #[test]
+    fn quiescence_completes_the_five() {
+        let mut board = board_from(&format!("{} a07", BROKEN_FOUR));
+        let mut data = new_data();
+        let mut params = Params::new();
+        let score = quiescence(&mut board, &mut data, &params, Score::MIN, Score::MAX, 0, 0);
+
+        assert_eq!(score, Score::mate_in(1));
+
+        let mut data = new_data();
+
+        params.set_depth(1);
+        iterative_search(&board, &params, &mut data, |_, _, _| Ok(())).unwrap();
+        assert_eq!(data.bestmove().to_string(), "k08");
+    }
+
+    // Trying every four at every quiescence ply used to take 843014 nodes on
+    // this bench position at depth 4.
+    #[test]
+    fn quiescence_stays_within_a_node_budget() {
+        let board = board_from("j10 k11 i11 h12 k09 l08 i09 h08 j09 j08 i10 l10");
+        let mut data = SearchData::with_defaults(Arc::new(AtomicBool::new(false)));
+        let mut params = Params::new();
+
+        data.set_tt(Arc::new(TranspositionTable::new(1)));
+        params.set_depth(4);
+        iterative_search(&board, &params, &mut data, |_, _, _| Ok(())).unwrap();
+        assert!(data.node_count() < 20_000, "{} nodes", data.node_count());
+    }
+
+    #[test]
+    fn threes_are_reduced_less() {
+        let pruning = PruningParams::new();
+
+        assert_eq!(pruning.reduction(8, 20, 0), 2);
+        assert_eq!(pruning.reduction(8, 20, 1), 1);
+        assert_eq!(pruning.reduction(8, 20, 2), 1);
+        assert_eq!(pruning.reduction(8, 1, 2), 0);
+    }
+
+    #[test]
+    fn plays_a_legal_move_when_stopped_before_the_first_iteration() {
+        let board = board_from("a01 j10 k10");
+        let stop = Arc::new(AtomicBool::new(true));
+        let mut data = SearchData::with_stop(stop.clone());
+        let mut params = Params::new();
+
+        data.set_tt(Arc::new(TranspositionTable::new(1)));
+        iterative_search(&board, &params, &mut data, |_, _, _| Ok(())).unwrap();
+        assert_eq!(data.completed_depth(), 0);
+        assert_eq!(board.stone_at(data.bestmove()), Stone::Empty);
+
+        let mut data = SearchData::with_stop(stop);
+
+        data.set_tt(Arc::new(TranspositionTable::new(1)));
+        params.set_searchmoves(&["a01".parse().unwrap(), "s19".parse().unwrap()]);
+        iterative_search(&board, &params, &mut data, |_, _, _| Ok(())).unwrap();
+        assert_eq!(data.bestmove().to_string(), "s19");
+    }
+
+    #[test]
+    fn helpers_skip_different_depths() {
+        let skipped = |thread_idx| {
+            let mut data = new_data();
+
+            data.thread_idx = thread_idx;
+            (1..=12)
+                .filter(|&depth| data.skips_depth(depth))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(skipped(0).is_empty());
+
+        for (a, b) in [(1, 2), (2, 3), (3, 5), (1, 7)] {
+            assert_ne!(skipped(a), skipped(b), "threads {} and {}", a, b);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::io;
+use std::sync::{
+    atomic::{AtomicU64, AtomicU8, Ordering},
+    Arc, RwLock,
+};
+
+use super::score::{Score, ScoreKind};
+use crate::core::types::Square;
+
+lazy_static! {
+    pub static ref TT: RwLock<Arc<TranspositionTable>> = RwLock::new(Arc::new(
+        TranspositionTable::new(TranspositionTable::DEFAULT_MB)
+    ));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Upper,
+    Lower,
+    Exact,
+}
+
+// Entries are stored as two atomic words, the key being xored with the data so
+// that torn writes from concurrent threads are detected as key mismatches.
+// The data word packs the move (16 bits, `NO_MOVE` for none), the score (16
+// bits), the depth (16 bits), the bound (8 bits) and the age (8 bits).
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+const NO_MOVE: u64 = 0xFFFF;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    bestmove: Option<Square>,
+    score: Score,
+    depth: u16,
+    bound: Bound,
+}
+
+impl Entry {
+    pub fn bestmove(&self) -> Option<Square> {
+        self.bestmove
+    }
+
+    // Converts the stored score back to a root-relative one.
+    pub fn score(&self, ply: u16) -> Score {
+        match self.score.kind() {
+            ScoreKind::MateIn(p) => Score::mate_in(p.saturating_add(ply as u8)),
+            ScoreKind::MatedIn(p) => Score::mated_in(p.saturating_add(ply as u8)),
+            ScoreKind::Centipoint(_) => self.score,
+        }
+    }
+
+    pub fn depth(&self) -> u16 {
+        self.depth
+    }
+
+    pub fn bound(&self) -> Bound {
+        self.bound
+    }
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    age: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub const DEFAULT_MB: usize = 16;
+
+    pub fn new(megabytes: usize) -> Self {
+        Self::try_new(megabytes).expect("cannot allocate the transposition table")
+    }
+
+    // Fails instead of aborting when the memory cannot be allocated, for sizes
+    // requested by the user.
+    pub fn try_new(megabytes: usize) -> io::Result<Self> {
+        let count = megabytes.max(1).saturating_mul(1 << 20) / std::mem::size_of::<Slot>();
+        let mut slots = Vec::new();
+
+        slots.try_reserve_exact(count).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                format!("cannot allocate a {} MB hash table", megabytes),
+            )
+        })?;
+        slots.extend((0..count).map(|_| Slot {
+            key: AtomicU64::new(0),
+            data: AtomicU64::new(0),
+        }));
+
+        Ok(Self {
+            slots,
+            age: AtomicU8::new(0),
+        })
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[((key as u128 * self.slots.len() as u128) >> 64) as usize]
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn new_search(&self) {
+        self.age.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+
+        if data == 0 || slot.key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+
+        let mv = data & 0xFFFF;
+
+        Some(Entry {
+            bestmove: (mv != NO_MOVE).then(|| Square::new(mv as u16)),
+            score: Score::cp((data >> 16) as u16 as i16),
+            depth: (data >> 32) as u16,
+            bound: match (data >> 48) as u8 {
+                1 => Bound::Upper,
+                2 => Bound::Lower,
+                _ => Bound::Exact,
+            },
+        })
+    }
+
+    pub fn store(
+        &self,
+        key: u64,
+        bestmove: Option<Square>,
+        score: Score,
+        depth: u16,
+        bound: Bound,
+        ply: u16,
+    ) {
+        let slot = self.slot(key);
+        let age = self.age.load(Ordering::Relaxed);
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_key = slot.key.load(Ordering::Relaxed) ^ old_data;
+
+        // Keep deeper entries of the same search for other positions.
+        if old_key != key && (old_data >> 56) as u8 == age && (old_data >> 32) as u16 > depth {
+            return;
+        }
+
+        // Stores mate scores relative to the current node instead of the root.
+        let score = match score.kind() {
+            ScoreKind::MateIn(p) => Score::mate_in(p.saturating_sub(ply as u8)),
+            ScoreKind::MatedIn(p) => Score::mated_in(p.saturating_sub(ply as u8)),
+            ScoreKind::Centipoint(_) => score,
+        };
+
+        let data = bestmove.map_or(NO_MOVE, |mv| mv.value() as u64)
+            | (score.value() as u16 as u64) << 16
+            | (depth as u64) << 32
+            | (match bound {
+                Bound::Upper => 1u64,
+                Bound::Lower => 2,
+                Bound::Exact => 3,
+            }) << 48
+            | (age as u64) << 56;
+
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // Returns the permill of sampled slots written during the current search.
+    pub fn hashfull(&self) -> u16 {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = self.slots.len().min(1000);
+
+        (self.slots[..sample]
+            .iter()
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+
+                data != 0 && (data >> 56) as u8 == age
+            })
+            .count()
+            * 1000
+            / sample) as u16
+    }
+}
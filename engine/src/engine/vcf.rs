@@ -0,0 +1,91 @@
+use super::movegen::Tactic;
+use crate::core::{bitboard::BitboardIter, board::Board, magic, types::Square};
+
+// Searches a victory by continuous fours (VCF) for the side to move: every
+// attacking move creates a five threat, so that each reply of the defender is
+// forced. Returns the first move of the sequence found, if any within
+// `max_plies` plies.
+pub fn find_vcf(board: &mut Board, max_plies: u16) -> Option<Square> {
+    let fours = magic::side_fours(board, board.turn());
+
+    let candidates = match Tactic::detect(board) {
+        Some(Tactic::Win(mv)) => return Some(mv),
+        Some(Tactic::Lost(_)) => return None,
+        // Blocking is only part of a VCF if the block makes a four.
+        Some(Tactic::Block(mv)) if fours.get_square(mv) => vec![mv],
+        Some(Tactic::Block(_)) => return None,
+        None => BitboardIter::from_bitboard(&fours).collect(),
+    };
+
+    if max_plies < 3 {
+        return None;
+    }
+
+    candidates
+        .into_iter()
+        .find(|&mv| wins_after_four(board, mv, max_plies))
+}
+
+fn wins_after_four(board: &mut Board, mv: Square, max_plies: u16) -> bool {
+    board.push(mv);
+
+    let wins = match Tactic::detect(board) {
+        // Two five threats cannot both be blocked.
+        Some(Tactic::Lost(_)) => true,
+        Some(Tactic::Block(block)) => {
+            board.push(block);
+
+            let wins = find_vcf(board, max_plies - 2).is_some();
+
+            board.pop(block);
+            wins
+        }
+        // The defender completes a five first, or the move made no four.
+        Some(Tactic::Win(_)) | None => false,
+    };
+
+    board.pop(mv);
+    wins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_from(moves: &[&str]) -> Board {
+        let mut board = Board::new();
+
+        for mv in moves {
+            board.push(mv.parse().unwrap());
+        }
+
+        board
+    }
+
+    #[test]
+    fn finds_double_four() {
+        let mut board = board_from(&[
+            "j10", "i10", "k10", "m14", "l10", "a01", "m11", "s01", "m12", "a19", "m13", "s19",
+        ]);
+
+        assert_eq!(find_vcf(&mut board, 3), Some("m10".parse().unwrap()));
+    }
+
+    #[test]
+    fn ignores_a_single_closed_four() {
+        let mut board = board_from(&["j10", "i10", "k10", "a01", "l10", "s01", "b19", "s19"]);
+
+        assert_eq!(find_vcf(&mut board, 21), None);
+    }
+
+    #[test]
+    fn finds_a_vcf_of_two_fours() {
+        // m10 forces n10, then m11 makes an open four.
+        let mut board = board_from(&[
+            "j10", "i10", "k10", "a01", "l10", "s01", "m12", "a19", "m13", "s19",
+        ]);
+
+        assert_eq!(find_vcf(&mut board, 5), Some("m10".parse().unwrap()));
+        assert_eq!(find_vcf(&mut board, 3), None);
+    }
+}
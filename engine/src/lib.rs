@@ -0,0 +1,14 @@
+//! Kinko is a gomoku engine. Besides the `kinko` binary speaking the UGMI
+//! protocol, the crate exposes the board representation, the search and an
+//! [`Engine`] type to embed the engine in other programs.
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod api;
+pub mod core;
+pub mod engine;
+pub mod tools;
+pub mod ugmi;
+
+pub use api::{Engine, Limits, Progress, SearchResult, StopHandle};
@@ -1,11 +1,14 @@
-#[macro_use]
-extern crate lazy_static;
-
-pub mod core;
-pub mod engine;
-pub mod ugmi;
-
-use crate::ugmi::client::Client;
+use kinko::core;
+use kinko::tools::{
+    analyze::{self, AnalyzeParams},
+    bench::{self, BenchParams},
+    bookgen::{self, BookgenParams},
+    datagen::{self, DatagenParams},
+    engine_match::{self, MatchParams},
+    perft::{self, PerftParams},
+    tune::{self, TuneParams},
+};
+use kinko::ugmi::client::Client;
 
 fn main() -> std::io::Result<()> {
     let _cross = core::magic::CrossAlignment::from(
@@ -13,7 +16,16 @@ fn main() -> std::io::Result<()> {
         core::types::Square::new(180),
     );
 
-    let client = Client::new();
+    let mut args = std::env::args().skip(1);
 
-    client.run()
+    match args.next().as_deref() {
+        Some("datagen") => datagen::run(&DatagenParams::from_args(args)?),
+        Some("analyze") => analyze::run(&AnalyzeParams::from_args(args)?),
+        Some("bench") => bench::run(&BenchParams::from_args(args)?),
+        Some("bookgen") => bookgen::run(&BookgenParams::from_args(args)?),
+        Some("match") => engine_match::run(&MatchParams::from_args(args)?),
+        Some("perft") => perft::run(&PerftParams::from_args(args)?),
+        Some("tune") => tune::run(&TuneParams::from_args(args)?),
+        _ => Client::new().run(),
+    }
 }
@@ -0,0 +1,401 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::sync::{atomic::AtomicBool, Arc};
+
+use crate::core::{
+    board::Board,
+    record::{GameResult, Record},
+    types::{Square, Stone},
+};
+use crate::engine::{
+    eval,
+    movegen::Tactic,
+    score::{Score, ScoreKind},
+    search::{self, Params, SearchData},
+    tt::TranspositionTable,
+    vcf,
+};
+
+pub struct AnalyzeParams {
+    record: String,
+    json: Option<String>,
+    depth: Option<u16>,
+    nodes: Option<u64>,
+    threshold: i32,
+    vcf_plies: u16,
+}
+
+impl AnalyzeParams {
+    pub fn from_args<I>(mut args: I) -> io::Result<Self>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut params = Self {
+            record: args
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing record"))?,
+            json: None,
+            depth: None,
+            nodes: None,
+            threshold: 150,
+            vcf_plies: 21,
+        };
+
+        while let Some(key) = args.next() {
+            let value = args.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("missing value for {}", key),
+                )
+            })?;
+            let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("bad {}", key));
+
+            match key.as_str() {
+                "json" => params.json = Some(value),
+                "depth" => params.depth = Some(value.parse().map_err(|_| invalid())?),
+                "nodes" => params.nodes = Some(value.parse().map_err(|_| invalid())?),
+                "threshold" => params.threshold = value.parse().map_err(|_| invalid())?,
+                "vcf_plies" => params.vcf_plies = value.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(params)
+    }
+
+    fn search_params(&self) -> Params {
+        let mut params = Params::new();
+
+        match (self.depth, self.nodes) {
+            (None, None) => params.set_depth(6),
+            (depth, nodes) => {
+                if let Some(depth) = depth {
+                    params.set_depth(depth);
+                }
+                if let Some(nodes) = nodes {
+                    params.set_nodes(nodes);
+                }
+            }
+        }
+
+        params
+    }
+}
+
+// The analysis of one move, all scores being from the point of view of the
+// side that played it.
+struct MoveAnalysis {
+    mv: Square,
+    side: Stone,
+    score: Score,
+    best: Square,
+    best_score: Score,
+    missed_vcf: Option<Square>,
+}
+
+// A move worse than the best one by more than the threshold, or changing the
+// mate status of the position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mistake {
+    Loses(i32),
+    AllowsMate(u8),
+    MissesMate(u8),
+}
+
+impl Display for Mistake {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Mistake::Loses(cp) => write!(f, "loses {}", cp),
+            Mistake::AllowsMate(plies) => write!(f, "allows mate in {}", plies.div_ceil(2)),
+            Mistake::MissesMate(plies) => write!(f, "misses mate in {}", plies.div_ceil(2)),
+        }
+    }
+}
+
+impl MoveAnalysis {
+    // Only centipoint scores can be subtracted, mate scores being encoded
+    // near the bounds.
+    fn loss(&self) -> Option<i32> {
+        match (self.best_score.kind(), self.score.kind()) {
+            (ScoreKind::Centipoint(best), ScoreKind::Centipoint(score)) => {
+                Some((best as i32 - score as i32).max(0))
+            }
+            _ => None,
+        }
+    }
+
+    fn mistake(&self, threshold: i32) -> Option<Mistake> {
+        match (self.best_score.kind(), self.score.kind()) {
+            (_, ScoreKind::MateIn(_)) | (ScoreKind::MatedIn(_), _) => None,
+            (ScoreKind::MateIn(plies), _) => Some(Mistake::MissesMate(plies)),
+            (_, ScoreKind::MatedIn(plies)) => Some(Mistake::AllowsMate(plies)),
+            _ => self
+                .loss()
+                .filter(|&loss| loss > threshold)
+                .map(Mistake::Loses),
+        }
+    }
+}
+
+fn search_position(
+    board: &Board,
+    params: &Params,
+    tt: &Arc<TranspositionTable>,
+) -> io::Result<(Square, Score)> {
+    let mut data = SearchData::with_stop(Arc::new(AtomicBool::new(false)));
+
+    data.set_tt(tt.clone());
+
+    let score = search::iterative_search(board, params, &mut data, |_, _, _| Ok(()))?;
+
+    Ok((data.bestmove(), score))
+}
+
+// Whether the side that just played `mv` still wins by continuous fours: the
+// move itself may be a four of another VCF than the one found before it.
+fn keeps_vcf(board: &mut Board, max_plies: u16) -> bool {
+    match Tactic::detect(board) {
+        Some(Tactic::Lost(_)) => true,
+        Some(Tactic::Block(block)) if max_plies > 2 => {
+            board.push(block);
+
+            let vcf = vcf::find_vcf(board, max_plies - 2).is_some();
+
+            board.pop(block);
+            vcf
+        }
+        _ => false,
+    }
+}
+
+fn analyze_game(record: &Record, params: &AnalyzeParams) -> io::Result<Vec<MoveAnalysis>> {
+    let search_params = params.search_params();
+    let tt = Arc::new(TranspositionTable::new(TranspositionTable::DEFAULT_MB));
+    let mut board = Board::new();
+    let mut analyses = Vec::new();
+    // Search result of the position before the current move, reused as the
+    // value of the previous move.
+    let mut current = search_position(&board, &search_params, &tt)?;
+
+    for &mv in record.moves.iter() {
+        let (best, best_score) = current;
+        let side = board.turn();
+        let vcf = vcf::find_vcf(&mut board, params.vcf_plies);
+
+        board.push(mv);
+
+        let score = if eval::is_victory(&board, mv) {
+            Score::mate_in(1)
+        } else if board.bitboard(Stone::Empty).is_empty() {
+            Score::DRAW
+        } else {
+            current = search_position(&board, &search_params, &tt)?;
+            -current.1
+        };
+
+        let missed_vcf = vcf.filter(|&first| {
+            first != mv
+                && !matches!(score.kind(), ScoreKind::MateIn(_))
+                && !keeps_vcf(&mut board, params.vcf_plies)
+        });
+
+        analyses.push(MoveAnalysis {
+            mv,
+            side,
+            score,
+            best,
+            best_score,
+            missed_vcf,
+        });
+
+        if eval::is_victory(&board, mv) {
+            break;
+        }
+    }
+
+    Ok(analyses)
+}
+
+fn side_name(side: Stone) -> &'static str {
+    match side {
+        Stone::White => "white",
+        _ => "black",
+    }
+}
+
+fn result_string(result: Option<GameResult>) -> &'static str {
+    match result {
+        Some(GameResult::BlackWins) => "1-0",
+        Some(GameResult::WhiteWins) => "0-1",
+        Some(GameResult::Draw) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+fn report(record: &Record, analyses: &[MoveAnalysis], threshold: i32) -> String {
+    let mut text = format!(
+        "Black: {}\nWhite: {}\nResult: {}\n\n",
+        record.black.as_deref().unwrap_or("?"),
+        record.white.as_deref().unwrap_or("?"),
+        result_string(record.outcome())
+    );
+
+    for (ply, analysis) in analyses.iter().enumerate() {
+        text += &format!(
+            "{:4}. {} {:5} eval {:>7}  best {} {:>7}",
+            ply + 1,
+            analysis.mv,
+            side_name(analysis.side),
+            analysis.score.to_string(),
+            analysis.best,
+            analysis.best_score.to_string()
+        );
+
+        if let Some(mistake) = analysis.mistake(threshold) {
+            text += &format!("  ?? {}", mistake);
+        }
+
+        if let Some(mv) = analysis.missed_vcf {
+            text += &format!("  missed VCF {}", mv);
+        }
+
+        text += "\n";
+    }
+
+    text
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped + "\""
+}
+
+fn json_score(score: Score) -> String {
+    match score.kind() {
+        ScoreKind::Centipoint(v) => format!("{{\"cp\": {}}}", v),
+        ScoreKind::MateIn(v) => format!("{{\"mate\": {}}}", v.div_ceil(2)),
+        ScoreKind::MatedIn(v) => format!("{{\"mate\": -{}}}", v.div_ceil(2)),
+    }
+}
+
+fn json(record: &Record, analyses: &[MoveAnalysis], threshold: i32) -> String {
+    let name = |name: &Option<String>| name.as_deref().map_or(String::from("null"), json_string);
+    let moves = analyses
+        .iter()
+        .enumerate()
+        .map(|(ply, analysis)| {
+            format!(
+                "    {{\"ply\": {}, \"move\": \"{}\", \"side\": \"{}\", \"eval\": {}, \
+                 \"best\": \"{}\", \"best_eval\": {}, \"loss\": {}, \"blunder\": {}, \
+                 \"mistake\": {}, \"missed_vcf\": {}}}",
+                ply + 1,
+                analysis.mv,
+                side_name(analysis.side),
+                json_score(analysis.score),
+                analysis.best,
+                json_score(analysis.best_score),
+                analysis
+                    .loss()
+                    .map_or(String::from("null"), |loss| loss.to_string()),
+                analysis.mistake(threshold).is_some(),
+                analysis
+                    .mistake(threshold)
+                    .map_or(String::from("null"), |mistake| {
+                        json_string(&mistake.to_string())
+                    }),
+                analysis
+                    .missed_vcf
+                    .map_or(String::from("null"), |mv| format!("\"{}\"", mv)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    format!(
+        "{{\n  \"black\": {},\n  \"white\": {},\n  \"result\": \"{}\",\n  \"moves\": [\n{}\n  ]\n}}\n",
+        name(&record.black),
+        name(&record.white),
+        result_string(record.outcome()),
+        moves.join(",\n")
+    )
+}
+
+pub fn run(params: &AnalyzeParams) -> io::Result<()> {
+    let record = Record::read(&params.record)?;
+    let analyses = analyze_game(&record, params)?;
+
+    print!("{}", report(&record, &analyses, params.threshold));
+
+    if let Some(path) = &params.json {
+        fs::write(path, json(&record, &analyses, params.threshold))?;
+        println!("\nAnalysis written to {}", path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Black to move has the VCF m10 n10 m11.
+    const VCF_POSITION: &str = "j10 i10 k10 a01 l10 s01 m12 a19 m13 s19";
+
+    fn analyses(moves: &str) -> Vec<MoveAnalysis> {
+        let record = Record {
+            moves: moves
+                .split_ascii_whitespace()
+                .map(|mv| mv.parse().unwrap())
+                .collect(),
+            ..Record::default()
+        };
+        let params =
+            AnalyzeParams::from_args(["-", "depth", "1"].into_iter().map(String::from)).unwrap();
+
+        analyze_game(&record, &params).unwrap()
+    }
+
+    fn last_analysis(moves: &str) -> MoveAnalysis {
+        analyses(moves).pop().unwrap()
+    }
+
+    #[test]
+    fn playing_the_vcf_misses_nothing() {
+        let analysis = last_analysis(&format!("{} m10", VCF_POSITION));
+
+        assert_eq!(analysis.missed_vcf, None);
+    }
+
+    #[test]
+    fn reports_a_missed_vcf() {
+        let analysis = last_analysis(&format!("{} b02", VCF_POSITION));
+
+        assert_eq!(analysis.missed_vcf, Some("m10".parse().unwrap()));
+    }
+
+    // m10 wins with an open four instead of the VCF i10 found first, and a05
+    // left the open three unblocked.
+    #[test]
+    fn mate_scores_are_not_subtracted() {
+        let analyses = analyses("j10 a01 k10 a03 l10 a05 m10");
+
+        assert_eq!(analyses[6].missed_vcf, None);
+        assert_eq!(analyses[6].mistake(150), None);
+        assert_eq!(analyses[5].loss(), None);
+        assert!(
+            matches!(analyses[5].mistake(150), Some(Mistake::AllowsMate(_))),
+            "{:?}",
+            analyses[5].mistake(150)
+        );
+    }
+}
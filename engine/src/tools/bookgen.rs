@@ -0,0 +1,114 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::core::{
+    record::{Format, Record},
+    types::Square,
+};
+use crate::engine::book::Book;
+
+pub struct BookgenParams {
+    games: String,
+    output: String,
+    depth: usize,
+    min_count: u32,
+}
+
+impl BookgenParams {
+    pub fn from_args<I>(mut args: I) -> io::Result<Self>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut params = Self {
+            games: args
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing games"))?,
+            output: args
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing output"))?,
+            depth: 12,
+            min_count: 2,
+        };
+
+        while let Some(key) = args.next() {
+            let value = args.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("missing value for {}", key),
+                )
+            })?;
+            let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("bad {}", key));
+
+            match key.as_str() {
+                "depth" => params.depth = value.parse().map_err(|_| invalid())?,
+                "min_count" => params.min_count = value.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+// Games are read from a directory of game records, from a single record, or
+// from a text file with one game per line, as the list of its moves from the
+// empty board separated by spaces (for example `j10 k11 k10`).
+pub fn read_games(path: &str) -> io::Result<Vec<Vec<Square>>> {
+    if Path::new(path).is_dir() {
+        let mut paths = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        paths.sort();
+
+        return paths
+            .iter()
+            .filter(|path| Format::from_path(path).is_some())
+            .map(|path| Record::read(path).map(|record| record.moves))
+            .collect();
+    }
+
+    if Format::from_path(path).is_some() {
+        return Ok(vec![Record::read(path)?.moves]);
+    }
+
+    let reader = BufReader::new(File::open(path)?);
+    let mut games = Vec::new();
+
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        games.push(
+            line.split_ascii_whitespace()
+                .map(|token| token.parse::<Square>().ok().filter(|sq| sq.is_valid()))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("ill-formed game at line {}", line_idx + 1),
+                    )
+                })?,
+        );
+    }
+
+    Ok(games)
+}
+
+pub fn run(params: &BookgenParams) -> io::Result<()> {
+    let games = read_games(&params.games)?;
+    let book = Book::from_games(&games, params.depth, params.min_count);
+
+    book.save(&params.output)?;
+    println!(
+        "Wrote {} entries from {} games to {}",
+        book.len(),
+        games.len(),
+        params.output
+    );
+    Ok(())
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::thread;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::core::{board::Board, types::Stone};
+use crate::engine::{
+    eval,
+    movegen::Movegen,
+    search::{self, Params, SearchData},
+    tt::TranspositionTable,
+};
+
+pub struct DatagenParams {
+    output: String,
+    games: u64,
+    threads: usize,
+    depth: Option<u16>,
+    nodes: Option<u64>,
+    random_plies: u16,
+    seed: u64,
+}
+
+impl DatagenParams {
+    pub fn from_args<I>(mut args: I) -> io::Result<Self>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut params = Self {
+            output: args
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing output"))?,
+            games: 1000,
+            threads: 1,
+            depth: None,
+            nodes: None,
+            random_plies: 4,
+            seed: 0,
+        };
+
+        while let Some(key) = args.next() {
+            let value = args.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("missing value for {}", key),
+                )
+            })?;
+            let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("bad {}", key));
+
+            match key.as_str() {
+                "games" => params.games = value.parse().map_err(|_| invalid())?,
+                "threads" => params.threads = value.parse().map_err(|_| invalid())?,
+                "depth" => params.depth = Some(value.parse().map_err(|_| invalid())?),
+                "nodes" => params.nodes = Some(value.parse().map_err(|_| invalid())?),
+                "random_plies" => params.random_plies = value.parse().map_err(|_| invalid())?,
+                "seed" => params.seed = value.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+
+        if params.threads == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "bad threads"));
+        }
+
+        Ok(params)
+    }
+
+    fn search_params(&self) -> Params {
+        let mut params = Params::new();
+
+        match (self.depth, self.nodes) {
+            (None, None) => params.set_depth(4),
+            (depth, nodes) => {
+                if let Some(depth) = depth {
+                    params.set_depth(depth);
+                }
+                if let Some(nodes) = nodes {
+                    params.set_nodes(nodes);
+                }
+            }
+        }
+
+        params
+    }
+}
+
+// Plays a single game, seeded by its index so that the output only depends on
+// the base seed and not on how games are spread across threads, and returns
+// the dataset lines for all searched positions.
+fn play_game(params: &DatagenParams, game_idx: u64) -> io::Result<Vec<String>> {
+    let mut rng = StdRng::seed_from_u64(params.seed.wrapping_add(game_idx));
+    let search_params = params.search_params();
+    let mut board = Board::new();
+    let mut records = Vec::new();
+    let mut winner = Stone::Empty;
+    // Each game gets its own table so that results do not depend on the
+    // games searched concurrently by other threads.
+    let tt = Arc::new(TranspositionTable::new(1));
+
+    for ply in 0.. {
+        if board.bitboard(Stone::Empty).is_empty() {
+            break;
+        }
+
+        let mut movegen = Movegen::new();
+
+        movegen.generate_near(&board);
+
+        let mv = if ply < params.random_plies {
+            movegen.select_random_move_with(&mut rng)
+        } else {
+            let mut data = SearchData::with_stop(Arc::new(AtomicBool::new(false)));
+
+            data.set_tt(tt.clone());
+            let score =
+                search::iterative_search(&board, &search_params, &mut data, |_, _, _| Ok(()))?;
+
+            if data.completed_depth() > 0 {
+                records.push((board.boardstring(), board.turn(), score));
+                data.bestmove()
+            } else {
+                movegen.order_moves(&board);
+                movegen.next().unwrap()
+            }
+        };
+
+        board.push(mv);
+
+        if eval::is_victory(&board, mv) {
+            winner = board.turn().flip();
+            break;
+        }
+    }
+
+    Ok(records
+        .into_iter()
+        .map(|(grid, turn, score)| {
+            let result = match winner {
+                Stone::Empty => "0.5",
+                w if w == turn => "1",
+                _ => "0",
+            };
+
+            format!(
+                "{} {} {} {}",
+                grid,
+                if turn == Stone::White { "w" } else { "b" },
+                result,
+                score.value()
+            )
+        })
+        .collect())
+}
+
+// Writes the games in the order of their indices whatever the order in which
+// threads finish them, holding back the games played ahead of a slower one.
+struct OrderedWriter<W: Write> {
+    writer: W,
+    next_game: u64,
+    pending: BTreeMap<u64, Vec<String>>,
+    positions: u64,
+}
+
+impl<W: Write> OrderedWriter<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            next_game: 0,
+            pending: BTreeMap::new(),
+            positions: 0,
+        }
+    }
+
+    fn write(&mut self, game_idx: u64, lines: Vec<String>) -> io::Result<()> {
+        self.pending.insert(game_idx, lines);
+
+        while let Some(lines) = self.pending.remove(&self.next_game) {
+            for line in lines.iter() {
+                writeln!(self.writer, "{}", line)?;
+            }
+
+            self.next_game += 1;
+            self.positions += lines.len() as u64;
+
+            if self.next_game.is_multiple_of(100) {
+                println!(
+                    "Played {} games, {} positions",
+                    self.next_game, self.positions
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn run(params: &DatagenParams) -> io::Result<()> {
+    let writer = Mutex::new(OrderedWriter::new(BufWriter::new(File::create(
+        &params.output,
+    )?)));
+    let next_game = AtomicU64::new(0);
+
+    thread::scope(|scope| {
+        let workers = (0..params.threads)
+            .map(|_| {
+                scope.spawn(|| -> io::Result<()> {
+                    loop {
+                        let game_idx = next_game.fetch_add(1, Ordering::Relaxed);
+
+                        if game_idx >= params.games {
+                            return Ok(());
+                        }
+
+                        let lines = play_game(params, game_idx)?;
+
+                        writer.lock().unwrap().write(game_idx, lines)?;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().unwrap())
+    })?;
+
+    let mut writer = writer.into_inner().unwrap();
+
+    writer.writer.flush()?;
+    println!(
+        "Wrote {} positions from {} games to {}",
+        writer.positions, params.games, params.output
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_games_in_index_order() {
+        let mut writer = OrderedWriter::new(Vec::new());
+
+        writer.write(2, vec![String::from("c")]).unwrap();
+        writer
+            .write(1, vec![String::from("b1"), String::from("b2")])
+            .unwrap();
+        assert!(writer.writer.is_empty());
+
+        writer.write(0, vec![String::from("a")]).unwrap();
+        writer.write(3, Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(writer.writer).unwrap(), "a\nb1\nb2\nc\n");
+        assert_eq!(writer.positions, 4);
+        assert!(writer.pending.is_empty());
+    }
+
+    #[test]
+    fn output_does_not_depend_on_threads() {
+        let dir = std::env::temp_dir();
+        let output = |threads: usize| {
+            let path = dir.join(format!(
+                "kinko-datagen-{}-{}.txt",
+                std::process::id(),
+                threads
+            ));
+            let args = [
+                path.to_str().unwrap(),
+                "games",
+                "4",
+                "threads",
+                &threads.to_string(),
+                "nodes",
+                "300",
+            ];
+            let params = DatagenParams::from_args(args.into_iter().map(String::from)).unwrap();
+
+            run(&params).unwrap();
+
+            let text = std::fs::read_to_string(&path).unwrap();
+
+            std::fs::remove_file(&path).unwrap();
+            text
+        };
+
+        assert_eq!(output(3), output(1));
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::core::board::Board;
+
+// A dataset is a text file with one position per line, formatted as
+// `<boardstring> <b|w> <result> [...]`, the result being 1, 0.5 or 0 from the
+// point of view of the side to move. Any trailing fields are ignored.
+pub struct Sample {
+    pub board: Board,
+    pub result: f64,
+}
+
+impl Sample {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_ascii_whitespace();
+        let grid = tokens.next()?;
+        let turn = tokens.next()?;
+        let result = tokens.next()?.parse::<f64>().ok()?;
+        let mut board = Board::new();
+
+        if !(0.0..=1.0).contains(&result) {
+            return None;
+        }
+
+        board.setup_position(grid, turn).ok()?;
+        Some(Self { board, result })
+    }
+}
+
+pub fn read_samples<P: AsRef<Path>>(path: P) -> io::Result<Vec<Sample>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut samples = Vec::new();
+
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        samples.push(Sample::parse(&line).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("ill-formed sample at line {}", line_idx + 1),
+            )
+        })?);
+    }
+
+    Ok(samples)
+}
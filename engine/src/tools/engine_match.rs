@@ -0,0 +1,559 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::bookgen;
+use crate::core::{
+    board::Board,
+    types::{Square, Stone},
+};
+use crate::engine::eval;
+
+pub struct MatchParams {
+    engines: [String; 2],
+    openings: String,
+    games: u32,
+    time: Duration,
+    inc: Duration,
+    movetime: Option<Duration>,
+    margin: Duration,
+    elo0: f64,
+    elo1: f64,
+    alpha: f64,
+    beta: f64,
+}
+
+impl MatchParams {
+    pub fn from_args<I>(mut args: I) -> io::Result<Self>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut next = |what: &str| {
+            args.next().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("missing {}", what))
+            })
+        };
+        let mut params = Self {
+            engines: [next("first engine")?, next("second engine")?],
+            openings: next("openings")?,
+            games: 100,
+            time: Duration::from_secs(10),
+            inc: Duration::from_millis(100),
+            movetime: None,
+            margin: Duration::from_millis(50),
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+
+        while let Some(key) = args.next() {
+            let value = args.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("missing value for {}", key),
+                )
+            })?;
+            let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("bad {}", key));
+            let millis = |value: &str| value.parse().map(Duration::from_millis);
+
+            match key.as_str() {
+                "games" => params.games = value.parse().map_err(|_| invalid())?,
+                "time" => params.time = millis(&value).map_err(|_| invalid())?,
+                "inc" => params.inc = millis(&value).map_err(|_| invalid())?,
+                "movetime" => params.movetime = Some(millis(&value).map_err(|_| invalid())?),
+                "margin" => params.margin = millis(&value).map_err(|_| invalid())?,
+                "elo0" => params.elo0 = value.parse().map_err(|_| invalid())?,
+                "elo1" => params.elo1 = value.parse().map_err(|_| invalid())?,
+                "alpha" => params.alpha = value.parse().map_err(|_| invalid())?,
+                "beta" => params.beta = value.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+
+        if params.elo0 >= params.elo1
+            || !(0.0..0.5).contains(&params.alpha)
+            || !(0.0..0.5).contains(&params.beta)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SPRT needs elo0 < elo1 and error rates below 0.5",
+            ));
+        }
+
+        Ok(params)
+    }
+}
+
+// A UGMI engine running as a child process. Its output is read by a separate
+// thread, so that waiting for a line can time out.
+struct Engine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    fn spawn(path: &str) -> io::Result<Self> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Self {
+            name: path.to_string(),
+            child,
+            stdin,
+            lines,
+        };
+
+        engine.send("ugmi")?;
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        loop {
+            match engine.read_line(deadline)? {
+                Some(line) if line.starts_with("id name ") => {
+                    engine.name = line["id name ".len()..].trim().to_string();
+                }
+                Some(line) if line.trim() == "ugmiok" => break,
+                Some(_) => (),
+                None => return Err(engine.error("no ugmiok")),
+            }
+        }
+
+        engine.sync()?;
+        Ok(engine)
+    }
+
+    fn error(&self, msg: &str) -> io::Error {
+        io::Error::other(format!("{}: {}", self.name, msg))
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    // Returns the next line of the engine, or None if the deadline passes
+    // first.
+    fn read_line(&mut self, deadline: Instant) -> io::Result<Option<String>> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => Ok(Some(line)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(self.error("engine exited")),
+        }
+    }
+
+    // Waits until the engine has processed all previous commands, dropping
+    // everything it printed in the meantime (such as a late bestmove).
+    fn sync(&mut self) -> io::Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        self.send("isready")?;
+
+        loop {
+            match self.read_line(deadline)? {
+                Some(line) if line.trim() == "readyok" => return Ok(()),
+                Some(_) => (),
+                None => return Err(self.error("no readyok")),
+            }
+        }
+    }
+
+    // Returns the move of the engine, or None if it did not answer in time.
+    fn bestmove(&mut self, deadline: Instant) -> io::Result<Option<String>> {
+        while let Some(line) = self.read_line(deadline)? {
+            let mut tokens = line.split_ascii_whitespace();
+
+            if tokens.next() == Some("bestmove") {
+                return Ok(Some(tokens.next().unwrap_or("").to_string()));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Win(Stone),
+    Draw,
+}
+
+// Plays a game between the engines from the opening, the first one playing
+// black, and returns its outcome and why the game ended.
+fn play_game(
+    mut engines: [&mut Engine; 2],
+    opening: &[Square],
+    params: &MatchParams,
+) -> io::Result<(Outcome, String)> {
+    let mut board = Board::new();
+    let mut moves = opening.iter().map(|mv| mv.to_string()).collect::<Vec<_>>();
+    let mut clocks = [params.time; 2];
+
+    for &mv in opening {
+        board.push(mv);
+    }
+
+    for engine in engines.iter_mut() {
+        engine.send("ugminewgame")?;
+        engine.sync()?;
+    }
+
+    loop {
+        let side = board.turn();
+        let idx = if side == Stone::Black { 0 } else { 1 };
+        let engine = &mut *engines[idx];
+        let limit = params.movetime.unwrap_or(clocks[idx]);
+
+        engine.send(&format!("position startpos moves {}", moves.join(" ")))?;
+        engine.send(&match params.movetime {
+            Some(movetime) => format!("go movetime {}", movetime.as_millis()),
+            None => format!(
+                "go wtime {} btime {} winc {} binc {}",
+                clocks[1].as_millis(),
+                clocks[0].as_millis(),
+                params.inc.as_millis(),
+                params.inc.as_millis()
+            ),
+        })?;
+
+        let start = Instant::now();
+        let answer = engine.bestmove(start + limit + params.margin)?;
+        let elapsed = start.elapsed();
+
+        let Some(answer) = answer else {
+            engine.send("stop")?;
+            engine.sync()?;
+            return Ok((
+                Outcome::Win(side.flip()),
+                format!("{} loses on time", engine.name),
+            ));
+        };
+
+        if params.movetime.is_none() {
+            clocks[idx] = clocks[idx].saturating_sub(elapsed) + params.inc;
+        }
+
+        let mv = match answer.parse::<Square>() {
+            Ok(mv) if mv.is_valid() && board.stone_at(mv) == Stone::Empty => mv,
+            _ => {
+                return Ok((
+                    Outcome::Win(side.flip()),
+                    format!("{} plays the illegal move '{}'", engine.name, answer),
+                ))
+            }
+        };
+
+        board.push(mv);
+        moves.push(mv.to_string());
+
+        if eval::is_victory(&board, mv) {
+            return Ok((Outcome::Win(side), format!("{} makes five", engine.name)));
+        }
+
+        if board.bitboard(Stone::Empty).is_empty() {
+            return Ok((Outcome::Draw, String::from("board full")));
+        }
+    }
+}
+
+fn logistic(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// Results from the point of view of the first engine.
+#[derive(Clone, Copy, Debug, Default)]
+struct Stats {
+    wins: u32,
+    losses: u32,
+    draws: u32,
+}
+
+impl Stats {
+    fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    fn score(&self) -> f64 {
+        moments(self.wins as f64, self.losses as f64, self.draws as f64).0
+    }
+
+    // The variance of the result of a single game.
+    fn variance(&self) -> f64 {
+        moments(self.wins as f64, self.losses as f64, self.draws as f64).1
+    }
+
+    // The Elo difference and the half-width of its 95% confidence interval.
+    fn elo(&self) -> (f64, f64) {
+        let score = self.score();
+        let deviation = 1.96 * (self.variance() / self.games() as f64).sqrt();
+        let low = elo((score - deviation).max(0.0));
+        let high = elo((score + deviation).min(1.0));
+
+        let error = (high - low) / 2.0;
+
+        // With only wins or only losses, both the Elo and the interval are
+        // unbounded.
+        (
+            elo(score),
+            if error.is_nan() { f64::INFINITY } else { error },
+        )
+    }
+
+    // The log-likelihood ratio of H1 (elo1) against H0 (elo0), using the
+    // normal approximation of the game results. Half a game is added to each
+    // result, so that the variance stays positive after one-sided results.
+    fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+
+        let (wins, losses, draws) = (
+            self.wins as f64 + 0.5,
+            self.losses as f64 + 0.5,
+            self.draws as f64 + 0.5,
+        );
+        let (score, variance) = moments(wins, losses, draws);
+        let (s0, s1) = (logistic(elo0), logistic(elo1));
+
+        (wins + losses + draws) * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance)
+    }
+}
+
+// The mean and the variance of the result of a single game.
+fn moments(wins: f64, losses: f64, draws: f64) -> (f64, f64) {
+    let games = wins + losses + draws;
+    let score = (wins + draws / 2.0) / games;
+    let variance =
+        (wins * (1.0 - score).powi(2) + losses * score.powi(2) + draws * (0.5 - score).powi(2))
+            / games;
+
+    (score, variance)
+}
+
+// Checks that the opening can be played: every square is empty when played
+// and neither side already has a five.
+fn check_opening(opening: &[Square]) -> Result<(), String> {
+    let mut board = Board::new();
+
+    for &mv in opening {
+        if board.stone_at(mv) != Stone::Empty {
+            return Err(format!("{} is already occupied", mv));
+        }
+
+        board.push(mv);
+
+        if eval::is_victory(&board, mv) {
+            return Err(format!("{} makes five", mv));
+        }
+    }
+
+    if board.bitboard(Stone::Empty).is_empty() {
+        return Err(String::from("the board is full"));
+    }
+
+    Ok(())
+}
+
+pub fn run(params: &MatchParams) -> io::Result<()> {
+    let mut openings = bookgen::read_games(&params.openings)?;
+
+    if openings.is_empty() {
+        openings.push(Vec::new());
+    }
+
+    for (idx, opening) in openings.iter().enumerate() {
+        check_opening(opening).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad opening {}: {}", idx + 1, err),
+            )
+        })?;
+    }
+
+    let mut first = Engine::spawn(&params.engines[0])?;
+    let mut second = Engine::spawn(&params.engines[1])?;
+
+    if first.name == second.name {
+        first.name = params.engines[0].clone();
+        second.name = params.engines[1].clone();
+    }
+    let mut stats = Stats::default();
+    let lower = (params.beta / (1.0 - params.alpha)).ln();
+    let upper = ((1.0 - params.beta) / params.alpha).ln();
+
+    println!(
+        "{} vs {}, SPRT elo0 {} elo1 {} alpha {} beta {} (bounds [{:.2}, {:.2}])",
+        first.name, second.name, params.elo0, params.elo1, params.alpha, params.beta, lower, upper
+    );
+
+    for game_idx in 0..params.games {
+        // Each opening is played twice in a row, with colors swapped.
+        let opening = &openings[(game_idx / 2) as usize % openings.len()];
+        let first_is_black = game_idx % 2 == 0;
+        let engines = if first_is_black {
+            [&mut first, &mut second]
+        } else {
+            [&mut second, &mut first]
+        };
+        let (black, white) = (engines[0].name.clone(), engines[1].name.clone());
+        let (outcome, reason) = play_game(engines, opening, params)?;
+
+        match (outcome, first_is_black) {
+            (Outcome::Draw, _) => stats.draws += 1,
+            (Outcome::Win(Stone::Black), true) | (Outcome::Win(Stone::White), false) => {
+                stats.wins += 1
+            }
+            _ => stats.losses += 1,
+        }
+
+        let (elo, error) = stats.elo();
+        let llr = stats.llr(params.elo0, params.elo1);
+
+        println!(
+            "Game {} ({} vs {}): {} {{{}}}",
+            game_idx + 1,
+            black,
+            white,
+            match outcome {
+                Outcome::Win(Stone::Black) => "1-0",
+                Outcome::Win(_) => "0-1",
+                Outcome::Draw => "1/2-1/2",
+            },
+            reason
+        );
+        println!(
+            "Score of {} vs {}: {} - {} - {}  Elo {:.1} +/- {:.1}  LLR {:.2}",
+            first.name, second.name, stats.wins, stats.losses, stats.draws, elo, error, llr
+        );
+
+        if llr >= upper {
+            println!("SPRT: H1 accepted");
+            return Ok(());
+        }
+
+        if llr <= lower {
+            println!("SPRT: H0 accepted");
+            return Ok(());
+        }
+    }
+
+    println!("SPRT: inconclusive after {} games", stats.games());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elo_of_even_results_is_zero() {
+        let stats = Stats {
+            wins: 30,
+            losses: 30,
+            draws: 40,
+        };
+        let (elo, error) = stats.elo();
+
+        assert!(elo.abs() < 1e-9);
+        assert!(error > 0.0 && error < 100.0);
+        assert!(stats.llr(0.0, 5.0) < 0.0);
+    }
+
+    #[test]
+    fn llr_grows_with_a_winning_score() {
+        let stats = Stats {
+            wins: 700,
+            losses: 300,
+            draws: 0,
+        };
+
+        assert!((logistic(elo(0.6)) - 0.6).abs() < 1e-9);
+        assert!(stats.llr(0.0, 5.0) > ((1.0 - 0.05) / 0.05f64).ln());
+    }
+
+    #[test]
+    fn llr_handles_one_sided_results() {
+        let wins = Stats {
+            wins: 3,
+            losses: 0,
+            draws: 0,
+        };
+        let losses = Stats {
+            wins: 0,
+            losses: 3,
+            draws: 0,
+        };
+        let bounds = (0.05 / 0.95f64).ln()..(0.95 / 0.05f64).ln();
+
+        assert!(wins.llr(0.0, 5.0) > 0.0);
+        assert!(bounds.contains(&wins.llr(0.0, 5.0)));
+        assert!(losses.llr(0.0, 5.0) < 0.0);
+        assert!(bounds.contains(&losses.llr(0.0, 5.0)));
+
+        let many_wins = Stats {
+            wins: 3000,
+            losses: 0,
+            draws: 0,
+        };
+
+        assert!(many_wins.llr(0.0, 5.0) > bounds.end);
+    }
+
+    #[test]
+    fn rejects_unplayable_openings() {
+        let opening = |moves: &str| {
+            moves
+                .split_ascii_whitespace()
+                .map(|mv| mv.parse().unwrap())
+                .collect::<Vec<Square>>()
+        };
+
+        assert!(check_opening(&opening("j10 k10 j11")).is_ok());
+        assert!(check_opening(&opening("j10 k10 j10")).is_err());
+        assert!(check_opening(&opening("j10 a01 k10 a02 l10 a03 m10 a04 n10")).is_err());
+    }
+}
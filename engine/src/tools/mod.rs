@@ -0,0 +1,8 @@
+pub mod analyze;
+pub mod bench;
+pub mod bookgen;
+pub mod datagen;
+pub mod dataset;
+pub mod engine_match;
+pub mod perft;
+pub mod tune;
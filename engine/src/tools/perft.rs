@@ -0,0 +1,250 @@
+use std::io;
+use std::time::Instant;
+
+use crate::core::{board::Board, types::Square};
+use crate::engine::{eval, movegen::Movegen};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Generator {
+    Near,
+    Far,
+    All,
+}
+
+impl Generator {
+    fn generate(self, board: &Board) -> Movegen {
+        let mut movegen = Movegen::new();
+
+        match self {
+            Self::Near => movegen.generate_near(board),
+            Self::Far => movegen.generate_far(board),
+            Self::All => movegen.generate_all(board),
+        }
+
+        movegen
+    }
+}
+
+pub struct PerftParams {
+    generator: Generator,
+    depth: u16,
+    moves: Vec<Square>,
+}
+
+impl PerftParams {
+    pub fn from_args<I>(mut args: I) -> io::Result<Self>
+    where
+        I: Iterator<Item = String>,
+    {
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidInput, what.to_string());
+
+        let generator = match args.next().as_deref() {
+            Some("near") => Generator::Near,
+            Some("far") => Generator::Far,
+            Some("all") => Generator::All,
+            _ => return Err(invalid("expected near, far or all")),
+        };
+        let depth = args
+            .next()
+            .and_then(|depth| depth.parse().ok())
+            .ok_or_else(|| invalid("bad depth"))?;
+        let moves = args
+            .map(|mv| mv.parse::<Square>().ok().filter(|sq| sq.is_valid()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("bad move"))?;
+
+        Ok(Self {
+            generator,
+            depth,
+            moves,
+        })
+    }
+}
+
+// Counts the leaves of the tree of moves produced by the generator, a position
+// where the last move made a five being a leaf.
+pub fn perft(board: &mut Board, generator: Generator, depth: u16) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut nodes = 0;
+
+    for mv in generator.generate(board) {
+        board.push(mv);
+        nodes += if eval::is_victory(board, mv) {
+            1
+        } else {
+            perft(board, generator, depth - 1)
+        };
+        board.pop(mv);
+    }
+
+    nodes
+}
+
+pub fn run(params: &PerftParams) -> io::Result<()> {
+    let mut board = Board::new();
+
+    for &mv in params.moves.iter() {
+        board.push(mv);
+    }
+
+    let start = Instant::now();
+    let mut total = 0;
+
+    // Like the usual perft "divide", the count below each root move helps
+    // finding which subtree disagrees with a reference.
+    for mv in params.generator.generate(&board) {
+        board.push(mv);
+
+        let nodes = if params.depth <= 1 || eval::is_victory(&board, mv) {
+            1
+        } else {
+            perft(&mut board, params.generator, params.depth - 1)
+        };
+
+        board.pop(mv);
+        println!("{}: {}", mv, nodes);
+        total += nodes;
+    }
+
+    if params.depth == 0 {
+        total = 1;
+    }
+
+    println!("\nNodes: {}", total);
+    println!("Time: {} ms", start.elapsed().as_millis());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{File, Rank, Stone, ROW_SIZE};
+
+    // The squares the generator should return, computed square by square
+    // without bitboards: the empty squares within `radius` of a stone (in
+    // king moves), all the empty squares for a zero radius, and the center on
+    // an empty board.
+    fn reference_moves(board: &Board, radius: i16) -> Vec<Square> {
+        let size = ROW_SIZE as i16;
+        let square =
+            |file: i16, rank: i16| Square::from(File::new(file as u8), Rank::new(rank as u8));
+        let mut moves = Vec::new();
+        let stone_at = |file: i16, rank: i16| {
+            (0..size).contains(&file)
+                && (0..size).contains(&rank)
+                && board.stone_at(square(file, rank)) != Stone::Empty
+        };
+
+        if radius > 0 && !(0..size).any(|rank| (0..size).any(|file| stone_at(file, rank))) {
+            return vec![square(9, 9)];
+        }
+
+        for rank in 0..size {
+            for file in 0..size {
+                if stone_at(file, rank) {
+                    continue;
+                }
+
+                let near = (-radius..=radius)
+                    .any(|dr| (-radius..=radius).any(|df| stone_at(file + df, rank + dr)));
+
+                if radius == 0 || near {
+                    moves.push(square(file, rank));
+                }
+            }
+        }
+
+        moves
+    }
+
+    fn reference_perft(board: &mut Board, radius: i16, depth: u16) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let mut nodes = 0;
+
+        for mv in reference_moves(board, radius) {
+            board.push(mv);
+            nodes += if eval::is_victory(board, mv) {
+                1
+            } else {
+                reference_perft(board, radius, depth - 1)
+            };
+            board.pop(mv);
+        }
+
+        nodes
+    }
+
+    fn board_from(moves: &[&str]) -> Board {
+        let mut board = Board::new();
+
+        for mv in moves {
+            board.push(mv.parse().unwrap());
+        }
+
+        board
+    }
+
+    // Stones on every edge and corner, where shifting bitboards could wrap
+    // around to the other side of the board.
+    fn edge_positions() -> Vec<Board> {
+        vec![
+            Board::new(),
+            board_from(&["j10"]),
+            board_from(&["a01"]),
+            board_from(&["s01", "a19"]),
+            board_from(&["s19", "a10", "s10"]),
+            board_from(&["j01", "j19", "b02", "r18"]),
+            board_from(&["a01", "a02", "a03", "a04", "s15", "s16", "s17", "s18"]),
+            board_from(&["a19", "b19", "c19", "d19", "s19", "s18", "s17", "r19"]),
+            // Both sides can complete a five, which ends the game.
+            board_from(&["j10", "a01", "k10", "a02", "l10", "a03", "m10", "a04"]),
+        ]
+    }
+
+    fn sorted(moves: Vec<Square>) -> Vec<u16> {
+        let mut values = moves.into_iter().map(|sq| sq.value()).collect::<Vec<_>>();
+
+        values.sort_unstable();
+        values
+    }
+
+    #[test]
+    fn generators_match_reference() {
+        for board in edge_positions() {
+            for (generator, radius) in [
+                (Generator::Near, 1),
+                (Generator::Far, 2),
+                (Generator::All, 0),
+            ] {
+                assert_eq!(
+                    sorted(generator.generate(&board).collect()),
+                    sorted(reference_moves(&board, radius)),
+                    "{:?} on\n{}",
+                    generator,
+                    board
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn perft_matches_reference() {
+        for mut board in edge_positions() {
+            for (generator, radius) in [(Generator::Near, 1), (Generator::Far, 2)] {
+                assert_eq!(
+                    perft(&mut board, generator, 3),
+                    reference_perft(&mut board, radius, 3),
+                    "{:?} on\n{}",
+                    generator,
+                    board
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::io;
+
+use super::dataset;
+use crate::engine::eval::{self, EvalParams};
+
+pub struct TuneParams {
+    dataset: String,
+    output: String,
+    weights: Option<String>,
+    iterations: usize,
+    rate: f64,
+}
+
+impl TuneParams {
+    pub fn from_args<I>(mut args: I) -> io::Result<Self>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut params = Self {
+            dataset: args
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing dataset"))?,
+            output: String::from("weights.txt"),
+            weights: None,
+            iterations: 1000,
+            rate: 1.0,
+        };
+
+        while let Some(key) = args.next() {
+            let value = args.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("missing value for {}", key),
+                )
+            })?;
+            let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("bad {}", key));
+
+            match key.as_str() {
+                "output" => params.output = value,
+                "weights" => params.weights = Some(value),
+                "iterations" => params.iterations = value.parse().map_err(|_| invalid())?,
+                "rate" => params.rate = value.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+struct Entry {
+    coeffs: Vec<i16>,
+    result: f64,
+}
+
+fn predict(weights: &[f64], entry: &Entry) -> f64 {
+    weights
+        .iter()
+        .zip(entry.coeffs.iter())
+        .map(|(&w, &c)| w * c as f64)
+        .sum()
+}
+
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+fn loss(entries: &[Entry], weights: &[f64], k: f64) -> f64 {
+    entries
+        .iter()
+        .map(|entry| (sigmoid(k, predict(weights, entry)) - entry.result).powi(2))
+        .sum::<f64>()
+        / entries.len() as f64
+}
+
+// Finds the scaling constant mapping evaluations to expected results that
+// best fits the initial weights, by ternary search.
+fn fit_scaling(entries: &[Entry], weights: &[f64]) -> f64 {
+    let (mut lo, mut hi) = (0.01, 10.0);
+
+    for _ in 0..100 {
+        let m1 = lo + (hi - lo) / 3.0;
+        let m2 = hi - (hi - lo) / 3.0;
+
+        if loss(entries, weights, m1) < loss(entries, weights, m2) {
+            hi = m2;
+        } else {
+            lo = m1;
+        }
+    }
+
+    (lo + hi) / 2.0
+}
+
+fn gradient(entries: &[Entry], weights: &[f64], k: f64) -> Vec<f64> {
+    let mut grad = vec![0.0; weights.len()];
+    let scale = k * 10f64.ln() / 400.0;
+
+    for entry in entries {
+        let s = sigmoid(k, predict(weights, entry));
+        let factor = 2.0 * (s - entry.result) * s * (1.0 - s) * scale;
+
+        for (g, &c) in grad.iter_mut().zip(entry.coeffs.iter()) {
+            *g += factor * c as f64;
+        }
+    }
+
+    grad.iter_mut().for_each(|g| *g /= entries.len() as f64);
+    grad
+}
+
+// Adam optimiser, the learning rate being in centipoints per step.
+struct Adam {
+    m: Vec<f64>,
+    v: Vec<f64>,
+    steps: i32,
+}
+
+impl Adam {
+    const BETA1: f64 = 0.9;
+    const BETA2: f64 = 0.999;
+    const EPSILON: f64 = 1e-8;
+
+    fn new(len: usize) -> Self {
+        Self {
+            m: vec![0.0; len],
+            v: vec![0.0; len],
+            steps: 0,
+        }
+    }
+
+    fn step(&mut self, weights: &mut [f64], grad: &[f64], rate: f64) {
+        self.steps += 1;
+
+        for i in 0..weights.len() {
+            self.m[i] = Self::BETA1 * self.m[i] + (1.0 - Self::BETA1) * grad[i];
+            self.v[i] = Self::BETA2 * self.v[i] + (1.0 - Self::BETA2) * grad[i] * grad[i];
+
+            let m_hat = self.m[i] / (1.0 - Self::BETA1.powi(self.steps));
+            let v_hat = self.v[i] / (1.0 - Self::BETA2.powi(self.steps));
+
+            weights[i] -= rate * m_hat / (v_hat.sqrt() + Self::EPSILON);
+        }
+    }
+}
+
+fn rounded(weights: &[f64]) -> Vec<f64> {
+    weights
+        .iter()
+        .map(|w| w.round().clamp(i16::MIN as f64, i16::MAX as f64))
+        .collect()
+}
+
+pub fn run(params: &TuneParams) -> io::Result<()> {
+    let initial = match &params.weights {
+        Some(path) => EvalParams::load(path)?,
+        None => EvalParams::new(),
+    };
+
+    let entries = dataset::read_samples(&params.dataset)?
+        .iter()
+        .map(|sample| Entry {
+            coeffs: eval::coefficients(&sample.board),
+            result: sample.result,
+        })
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "empty dataset"));
+    }
+
+    let mut weights = initial
+        .values()
+        .iter()
+        .map(|&v| v as f64)
+        .collect::<Vec<_>>();
+    let k = fit_scaling(&entries, &weights);
+    let initial_loss = loss(&entries, &weights, k);
+
+    println!(
+        "Loaded {} positions, scaling constant K = {:.4}",
+        entries.len(),
+        k
+    );
+    println!("Initial loss: {:.8}", initial_loss);
+
+    let mut adam = Adam::new(weights.len());
+    let report_every = (params.iterations / 20).max(1);
+
+    for iter in 1..=params.iterations {
+        let grad = gradient(&entries, &weights, k);
+
+        adam.step(&mut weights, &grad, params.rate);
+
+        if iter % report_every == 0 || iter == params.iterations {
+            println!(
+                "Iteration {:6} loss {:.8}",
+                iter,
+                loss(&entries, &weights, k)
+            );
+        }
+    }
+
+    let weights = rounded(&weights);
+    let mut tuned = initial.clone();
+
+    tuned.set_values(&weights.iter().map(|&w| w as i16).collect::<Vec<_>>());
+    tuned.save(&params.output)?;
+
+    println!(
+        "Final loss: {:.8} (initial {:.8})",
+        loss(&entries, &weights, k),
+        initial_loss
+    );
+
+    for ((name, old), new) in EvalParams::names()
+        .zip(initial.values())
+        .zip(tuned.values())
+    {
+        println!("{:20} {:6} -> {:6}", name, old, new);
+    }
+
+    println!("Weights written to {}", params.output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Entries with a single coefficient, whose results follow the sigmoid of
+    // the given scaling constant.
+    fn entries_with_scaling(k: f64) -> Vec<Entry> {
+        (-20..=20)
+            .map(|c| Entry {
+                coeffs: vec![c],
+                result: sigmoid(k, c as f64 * 50.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fits_the_scaling_constant() {
+        for k in [0.5, 1.3, 4.0] {
+            let fitted = fit_scaling(&entries_with_scaling(k), &[50.0]);
+
+            assert!((fitted - k).abs() < 1e-3, "{} fitted as {}", k, fitted);
+        }
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        let entries = entries_with_scaling(1.3);
+        let (weight, h) = (30.0, 1e-3);
+        let numeric =
+            (loss(&entries, &[weight + h], 1.3) - loss(&entries, &[weight - h], 1.3)) / (2.0 * h);
+        let analytic = gradient(&entries, &[weight], 1.3)[0];
+
+        assert!(
+            (numeric - analytic).abs() < 1e-9,
+            "{} {}",
+            numeric,
+            analytic
+        );
+    }
+
+    #[test]
+    fn adam_steps_against_the_gradient() {
+        let mut adam = Adam::new(3);
+        let mut weights = vec![10.0, 10.0, 10.0];
+
+        // Bias correction makes the first step as large as the rate.
+        adam.step(&mut weights, &[0.5, -2e-4, 0.0], 2.0);
+
+        assert!((weights[0] - 8.0).abs() < 1e-6);
+        assert!((weights[1] - 12.0).abs() < 1e-3);
+        assert_eq!(weights[2], 10.0);
+
+        let entries = entries_with_scaling(1.3);
+        let mut adam = Adam::new(1);
+        let mut weights = vec![10.0];
+
+        for _ in 0..2000 {
+            let grad = gradient(&entries, &weights, 1.3);
+
+            adam.step(&mut weights, &grad, 1.0);
+        }
+
+        assert!((weights[0] - 50.0).abs() < 1.0, "{}", weights[0]);
+    }
+}
@@ -3,12 +3,22 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
-use crate::core::{board::Board, types::Square};
-use crate::engine::search::{self, Params};
+use crate::core::{
+    board::Board,
+    types::{Square, Stone},
+};
+use crate::engine::book::BOOK;
+use crate::engine::options::OPTIONS;
+use crate::engine::search::{self, Params, PONDER, STOP};
+use crate::engine::tt::TT;
+use crate::tools::bench::{self, BenchParams};
 
-pub struct Client {}
+pub struct Client {
+    search: Option<JoinHandle<io::Result<()>>>,
+}
 
 lazy_static! {
     pub static ref DEBUG: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
@@ -16,10 +26,10 @@ lazy_static! {
 
 impl Client {
     pub fn new() -> Self {
-        Self {}
+        Self { search: None }
     }
 
-    pub fn run(&self) -> io::Result<()> {
+    pub fn run(&mut self) -> io::Result<()> {
         let mut board = Board::new();
         let stdin = io::stdin();
         let mut buffer = String::new();
@@ -31,13 +41,15 @@ impl Client {
             match command {
                 "ugmi" => self.display_ugmi()?,
                 "d" => println!("{}", board),
+                "bench" => self.bench(tokens)?,
                 "debug" => self.select_debug(tokens)?,
                 "isready" => self.display_readyok()?,
-                "setoption" => (),
-                "ugminewgame" => (),
+                "setoption" => self.set_option(tokens)?,
+                "ugminewgame" => TT.read().unwrap().clear(),
                 "position" => self.set_position(&mut board, tokens)?,
                 "go" => self.new_search(tokens, &board)?,
-                "stop" => self.stop_search(),
+                "stop" => self.stop_search()?,
+                "ponderhit" => PONDER.store(false, Ordering::Relaxed),
                 "quit" => break,
                 _ => println!("info string Error: unknown command '{}'", command),
             }
@@ -45,12 +57,13 @@ impl Client {
             buffer.clear();
         }
 
-        Ok(())
+        self.stop_search()
     }
 
     fn display_ugmi(&self) -> io::Result<()> {
         println!("id name Kinko v{}", env!("CARGO_PKG_VERSION"));
         println!("id author Morgan Houppin");
+        print!("{}", OPTIONS.read().unwrap());
         println!("ugmiok");
         io::stdout().flush()
     }
@@ -78,37 +91,102 @@ impl Client {
         Ok(())
     }
 
-    fn set_position<'a, I>(&self, board: &mut Board, mut tokens: I) -> io::Result<()>
+    fn bench<'a, I>(&mut self, tokens: I) -> io::Result<()>
     where
         I: Iterator<Item = &'a str>,
     {
-        match tokens.next() {
-            Some("startpos") => board.reset(),
-            Some("board") => {
-                board.setup_position(
-                    tokens
-                        .next()
-                        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?,
-                    tokens
-                        .next()
-                        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?,
-                );
+        self.stop_search()?;
+
+        match BenchParams::from_args(tokens.map(String::from)) {
+            Ok(params) => bench::run(&params)?,
+            Err(err) => println!("info string Error: {}", err),
+        }
+
+        io::stdout().flush()
+    }
+
+    fn set_option<'a, I>(&self, tokens: I) -> io::Result<()>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut name = Vec::new();
+        let mut value = Vec::new();
+        let mut current = None;
+
+        for token in tokens {
+            match token {
+                "name" => current = Some(&mut name),
+                "value" => current = Some(&mut value),
+                _ => {
+                    if let Some(field) = current.as_mut() {
+                        field.push(token);
+                    }
+                }
             }
-            _ => (),
         }
 
-        let _ = tokens.next();
+        let name = name.join(" ");
+        let value = (!value.is_empty()).then(|| value.join(" "));
+        let result = OPTIONS.write().unwrap().set(&name, value.as_deref());
 
-        for sq in tokens {
-            board.push(
-                sq.parse()
-                    .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?,
-            )
+        if let Err(err) = result {
+            println!("info string Error: cannot set option '{}': {}", name, err);
+            io::stdout().flush()?;
         }
 
         Ok(())
     }
 
+    fn set_position<'a, I>(&self, board: &mut Board, tokens: I) -> io::Result<()>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        // Ill-formed positions are ignored, keeping the previous one.
+        match self.parse_position(board, tokens) {
+            Ok(position) => *board = position,
+            Err(err) => println!("info string Error: {}", err),
+        }
+
+        io::stdout().flush()
+    }
+
+    fn parse_position<'a, I>(&self, board: &Board, mut tokens: I) -> io::Result<Board>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+        let mut position = board.clone();
+
+        match tokens.next() {
+            Some("startpos") => position.reset(),
+            Some("board") => position.setup_position(
+                tokens
+                    .next()
+                    .ok_or_else(|| invalid("missing board string"))?,
+                tokens
+                    .next()
+                    .ok_or_else(|| invalid("missing side to move"))?,
+            )?,
+            _ => return Err(invalid("expected startpos or board")),
+        }
+
+        match tokens.next() {
+            Some("moves") | None => (),
+            Some(token) => return Err(invalid(&format!("unexpected token '{}'", token))),
+        }
+
+        for token in tokens {
+            match token.parse::<Square>() {
+                Ok(sq) if sq.is_valid() && position.stone_at(sq) == Stone::Empty => {
+                    position.push(sq)
+                }
+                _ => return Err(invalid(&format!("illegal move '{}'", token))),
+            }
+        }
+
+        Ok(position)
+    }
+
     fn parse_millis(&self, token: Option<&str>) -> Option<Duration> {
         if let Some(token_str) = token {
             token_str.parse::<u64>().ok().map(Duration::from_millis)
@@ -117,11 +195,14 @@ impl Client {
         }
     }
 
-    fn new_search<'a, I>(&self, tokens: I, board: &Board) -> io::Result<()>
+    fn new_search<'a, I>(&mut self, tokens: I, board: &Board) -> io::Result<()>
     where
         I: Iterator<Item = &'a str>,
     {
         let mut params = Params::new();
+        let mut ponder = false;
+        let mut infinite = false;
+        let mut restricted = false;
 
         let tokens = tokens.collect::<Vec<_>>();
         let mut i = 0;
@@ -176,6 +257,8 @@ impl Client {
                         i += 1;
                     }
                 }
+                "ponder" => ponder = true,
+                "infinite" => infinite = true,
                 "searchmoves" => {
                     let mut moves = Vec::new();
                     while i < tokens.len() {
@@ -185,6 +268,7 @@ impl Client {
                         i += 1;
                     }
                     params.set_searchmoves(moves.as_slice());
+                    restricted = true;
                     break;
                 }
                 _ => break,
@@ -193,10 +277,46 @@ impl Client {
             i += 1;
         }
 
-        search::run_search(board, &params)
+        params.allocate_time(board.turn());
+
+        // A search still running would print its best move after ours.
+        self.stop_search()?;
+
+        // Analysis and restricted searches are answered by the search only.
+        if !ponder && !infinite && !restricted {
+            if let Some(mv) = self.book_move(board) {
+                println!("info string Book move");
+                println!("bestmove {}", mv);
+                return io::stdout().flush();
+            }
+        }
+
+        let board = board.clone();
+
+        STOP.store(false, Ordering::Relaxed);
+        PONDER.store(ponder, Ordering::Relaxed);
+        self.search = Some(thread::spawn(move || search::run_search(&board, &params)));
+        Ok(())
     }
 
-    fn stop_search(&self) {}
+    fn book_move(&self, board: &Board) -> Option<Square> {
+        if OPTIONS.read().unwrap().check("OwnBook") != Some(true) {
+            return None;
+        }
+
+        BOOK.read().unwrap().as_ref()?.pick(board)
+    }
+
+    // Stops the current search if any, waiting for it to send its best move.
+    fn stop_search(&mut self) -> io::Result<()> {
+        PONDER.store(false, Ordering::Relaxed);
+        STOP.store(true, Ordering::Relaxed);
+
+        match self.search.take() {
+            Some(search) => search.join().unwrap(),
+            None => Ok(()),
+        }
+    }
 }
 
 impl Default for Client {
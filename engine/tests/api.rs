@@ -0,0 +1,100 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use kinko::core::types::{Square, Stone};
+use kinko::engine::score::ScoreKind;
+use kinko::{Engine, Limits};
+
+fn moves(moves: &str) -> Vec<Square> {
+    moves
+        .split_ascii_whitespace()
+        .map(|mv| mv.parse().unwrap())
+        .collect()
+}
+
+#[test]
+fn searches_to_the_given_depth() {
+    let mut engine = Engine::new();
+    let depths = Arc::new(Mutex::new(Vec::new()));
+    let reported = depths.clone();
+
+    engine.set_position(&moves("j10 k11 k10")).unwrap();
+    engine.on_progress(move |progress| reported.lock().unwrap().push(progress.depth));
+
+    let result = engine.search(&Limits::depth(3)).unwrap();
+
+    assert_eq!(result.depth, 3);
+    assert_eq!(result.pv.first(), Some(&result.bestmove));
+    assert_eq!(engine.board().stone_at(result.bestmove), Stone::Empty);
+    assert!(result.nodes > 0);
+    assert_eq!(depths.lock().unwrap().last(), Some(&3));
+}
+
+#[test]
+fn finds_the_winning_move() {
+    let mut engine = Engine::new();
+
+    engine
+        .set_position(&moves("j10 a01 k10 a03 l10 a05 m10 a07"))
+        .unwrap();
+
+    let result = engine.search(&Limits::depth(2)).unwrap();
+
+    assert!(["i10", "n10"].contains(&result.bestmove.to_string().as_str()));
+    assert!(matches!(result.score.kind(), ScoreKind::MateIn(_)));
+}
+
+#[test]
+fn stops_from_another_thread() {
+    let mut engine = Engine::new();
+    let handle = engine.stop_handle();
+
+    engine.set_position(&moves("j10 k11")).unwrap();
+
+    let stopper = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        handle.stop();
+    });
+    let result = engine.search(&Limits::default()).unwrap();
+
+    stopper.join().unwrap();
+    assert!(result.depth > 0);
+    assert_eq!(engine.board().stone_at(result.bestmove), Stone::Empty);
+}
+
+#[test]
+fn plays_a_legal_move_without_a_completed_iteration() {
+    let mut engine = Engine::new();
+
+    engine.set_position(&moves("a01 j10 k10")).unwrap();
+
+    for limits in [Limits::depth(0), Limits::movetime(Duration::ZERO)] {
+        let result = engine.search(&limits).unwrap();
+
+        assert_eq!(result.depth, 0, "{:?}", limits);
+        assert_eq!(
+            engine.board().stone_at(result.bestmove),
+            Stone::Empty,
+            "{:?}",
+            limits
+        );
+    }
+}
+
+#[test]
+fn rejects_illegal_positions() {
+    let mut engine = Engine::new();
+
+    engine.set_position(&moves("j10")).unwrap();
+    assert!(engine.set_position(&moves("j10 j10")).is_err());
+    assert_eq!(
+        engine.board().stone_at("j10".parse().unwrap()),
+        Stone::Black
+    );
+
+    engine
+        .set_position(&moves("j10 a01 k10 a03 l10 a05 m10 a07 n10"))
+        .unwrap();
+    assert!(engine.search(&Limits::depth(1)).is_err());
+}
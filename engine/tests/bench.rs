@@ -0,0 +1,49 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Runs the UGMI commands and returns the total node count of the bench they
+// end with.
+fn bench_nodes(commands: &[&str]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kinko"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+
+    for command in commands {
+        writeln!(stdin, "{}", command).unwrap();
+    }
+
+    writeln!(stdin, "bench 3").unwrap();
+    writeln!(stdin, "quit").unwrap();
+    drop(stdin);
+
+    let output = String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap();
+
+    output
+        .lines()
+        .find(|line| line.starts_with("Total nodes: "))
+        .unwrap_or_else(|| panic!("no node count in {:?}", output))
+        .to_string()
+}
+
+#[test]
+fn signature_ignores_the_options() {
+    let default = bench_nodes(&[]);
+
+    assert_eq!(
+        bench_nodes(&[
+            "setoption name MultiPV value 3",
+            "setoption name Tempo value 500",
+            "setoption name LMRBase value -500",
+            "setoption name FutilityDepth value 0",
+            "setoption name NullMove value true",
+            "setoption name UseNNUE value true",
+            "setoption name Hash value 1",
+            "position startpos moves j10 k11",
+            "go depth 3",
+        ]),
+        default
+    );
+}
@@ -0,0 +1,94 @@
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn book_moves_are_not_played_in_analysis() {
+    let dir = env::temp_dir().join(format!("kinko-book-test-{}", std::process::id()));
+    let games = dir.join("games.txt");
+    let book = dir.join("book.bin");
+
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&games, "j10 k11 k10\n").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_kinko"))
+        .args(["bookgen", games.to_str().unwrap(), book.to_str().unwrap()])
+        .args(["min_count", "1"])
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kinko"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (sender, lines) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let expect = |stdin: &mut dyn Write, commands: &[&str], token: &str| {
+        for command in commands {
+            writeln!(stdin, "{}", command).unwrap();
+        }
+
+        let mut received = Vec::new();
+
+        loop {
+            let line = lines.recv_timeout(TIMEOUT).unwrap();
+            let done = line.starts_with(token);
+
+            received.push(line);
+
+            if done {
+                return received;
+            }
+        }
+    };
+
+    expect(
+        &mut stdin,
+        &[
+            &format!("setoption name BookFile value {}", book.display()),
+            "setoption name OwnBook value true",
+            "position startpos moves j10",
+            "isready",
+        ],
+        "readyok",
+    );
+
+    let answer = expect(&mut stdin, &["go infinite", "isready"], "readyok");
+
+    assert!(
+        answer.iter().all(|line| !line.starts_with("bestmove")),
+        "{:?}",
+        answer
+    );
+    expect(&mut stdin, &["stop"], "bestmove");
+
+    let answer = expect(&mut stdin, &["go depth 1"], "bestmove");
+
+    assert_eq!(answer.last().unwrap(), "bestmove k11", "{:?}", answer);
+
+    writeln!(stdin, "quit").unwrap();
+    child.wait().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,413 @@
+// Conformance tests of the UGMI protocol as described in doc/ugmi.md. They run
+// against Kinko by default, and against any other engine binary given with the
+// UGMI_ENGINE environment variable.
+
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    fn spawn() -> Self {
+        let path = env::var("UGMI_ENGINE").unwrap_or_else(|_| env!("CARGO_BIN_EXE_kinko").into());
+        let mut child = Command::new(&path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|err| panic!("cannot start {}: {}", path, err));
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            child,
+            stdin,
+            lines,
+        }
+    }
+
+    // Sends raw text, so that tests control whitespace and line endings.
+    fn send_raw(&mut self, text: &str) {
+        self.stdin.write_all(text.as_bytes()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn send(&mut self, command: &str) {
+        self.send_raw(&format!("{}\n", command));
+    }
+
+    fn read_line(&mut self, timeout: Duration) -> Option<String> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => Some(line),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => panic!("the engine exited"),
+        }
+    }
+
+    // Returns all lines up to the first one starting with the token, which is
+    // included last.
+    fn expect(&mut self, token: &str, timeout: Duration) -> Vec<String> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = self
+                .read_line(remaining)
+                .unwrap_or_else(|| panic!("no '{}' after {:?}, got {:?}", token, timeout, lines));
+            let done = line.split_ascii_whitespace().next() == Some(token);
+
+            lines.push(line);
+
+            if done {
+                return lines;
+            }
+        }
+    }
+
+    fn handshake(&mut self) -> Vec<String> {
+        self.send("ugmi");
+
+        let lines = self.expect("ugmiok", TIMEOUT);
+
+        self.send("isready");
+        self.expect("readyok", TIMEOUT);
+        lines
+    }
+
+    // Returns the move of the bestmove line ending the given lines.
+    fn bestmove(lines: &[String]) -> String {
+        let line = lines.last().unwrap();
+        let mut tokens = line.split_ascii_whitespace().skip(1);
+
+        tokens
+            .next()
+            .unwrap_or_else(|| panic!("no move in '{}'", line))
+            .to_string()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Checks the move format (a file from `a` to `s`, a rank from `01` to `19`) and
+// that the square is empty after the given moves.
+fn assert_legal(mv: &str, moves: &[&str]) {
+    let bytes = mv.as_bytes();
+    let rank = mv.get(1..).and_then(|rank| rank.parse::<u8>().ok());
+
+    assert!(
+        bytes.len() == 3 && (b'a'..=b's').contains(&bytes[0]) && matches!(rank, Some(1..=19)),
+        "ill-formed move '{}'",
+        mv
+    );
+    assert!(!moves.contains(&mv), "move '{}' on an occupied square", mv);
+}
+
+#[test]
+fn identifies_itself() {
+    let mut engine = Engine::spawn();
+    let lines = engine.handshake();
+    let ids = |kind: &str| {
+        lines
+            .iter()
+            .filter(|line| line.starts_with(&format!("id {} ", kind)))
+            .count()
+    };
+
+    assert_eq!(ids("name"), 1, "{:?}", lines);
+    assert_eq!(ids("author"), 1, "{:?}", lines);
+
+    for line in lines[..lines.len() - 1].iter() {
+        assert!(
+            line.starts_with("id ") || line.starts_with("option name "),
+            "unexpected line '{}' before ugmiok",
+            line
+        );
+    }
+}
+
+#[test]
+fn answers_isready_while_searching() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("position startpos moves j10 k11");
+    engine.send("go");
+    thread::sleep(Duration::from_millis(200));
+    engine.send("isready");
+
+    let lines = engine.expect("readyok", TIMEOUT);
+
+    assert!(
+        lines.iter().all(|line| !line.starts_with("bestmove")),
+        "infinite search sent a bestmove before stop: {:?}",
+        lines
+    );
+
+    engine.send("stop");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+
+    assert_legal(&Engine::bestmove(&lines), &["j10", "k11"]);
+}
+
+#[test]
+fn stop_without_search_is_ignored() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("stop");
+    engine.send("ponderhit");
+    engine.send("isready");
+
+    let lines = engine.expect("readyok", TIMEOUT);
+
+    assert!(
+        lines.iter().all(|line| !line.starts_with("bestmove")),
+        "{:?}",
+        lines
+    );
+}
+
+#[test]
+fn stops_before_the_first_iteration() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("position startpos moves a01 j10 k10");
+    engine.send("go");
+    engine.send("stop");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+
+    assert_legal(&Engine::bestmove(&lines), &["a01", "j10", "k10"]);
+}
+
+#[test]
+fn tolerates_whitespace() {
+    let mut engine = Engine::spawn();
+
+    engine.send_raw("  ugmi \t\r\n");
+    engine.expect("ugmiok", TIMEOUT);
+    engine.send_raw("\tdebug \t  on  \t\r\n");
+    engine.send_raw("isready\r\n");
+    engine.expect("readyok", TIMEOUT);
+    engine.send_raw("position \t startpos   moves\tj10  k10 \r\n");
+    engine.send_raw(" go\tdepth  2 \r\n");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+
+    assert_legal(&Engine::bestmove(&lines), &["j10", "k10"]);
+}
+
+#[test]
+fn ignores_ill_formed_commands() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+
+    for command in [
+        "",
+        "foo bar",
+        "position",
+        "position startpos moves zz99",
+        "position startpos moves A01",
+        "position startpos moves a00",
+        "position startpos moves a20",
+        "position startpos moves t01",
+        "position startpos moves j10 j10",
+        "position board 19/19 b",
+        "setoption",
+        "setoption name NoSuchOption value 3",
+        "debug maybe",
+        "go depth",
+        "go searchmoves A01",
+    ] {
+        engine.send(command);
+
+        // The answer to ill-formed commands may be a bestmove (for `go`), but
+        // the engine must still be alive and responsive.
+        engine.send("stop");
+        engine.send("isready");
+        engine.expect("readyok", TIMEOUT);
+    }
+
+    engine.send("position startpos moves j10 i09");
+    engine.send("go depth 2");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+
+    assert_legal(&Engine::bestmove(&lines), &["j10", "i09"]);
+}
+
+#[test]
+fn plays_legal_moves() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+
+    for moves in [
+        vec![],
+        vec!["j10"],
+        vec!["a01", "s19", "a19"],
+        vec!["j10", "k10", "j11", "k11", "j12", "k12"],
+        vec!["j10", "a01", "k10", "a02", "l10", "a03", "m10"],
+    ] {
+        engine.send("ugminewgame");
+        engine.send("isready");
+        engine.expect("readyok", TIMEOUT);
+        engine.send(&format!("position startpos moves {}", moves.join(" ")));
+        engine.send("go depth 3");
+
+        let lines = engine.expect("bestmove", TIMEOUT);
+
+        assert_legal(&Engine::bestmove(&lines), &moves);
+    }
+}
+
+#[test]
+fn reports_multiple_principal_variations() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("setoption name MultiPV value 3");
+    engine.send("position startpos moves j10 k11");
+    engine.send("go depth 2");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+    let mut first_moves = Vec::new();
+
+    for multipv in 1..=3 {
+        let line = lines
+            .iter()
+            .rev()
+            .find(|line| line.contains(&format!(" multipv {} ", multipv)))
+            .unwrap_or_else(|| panic!("no multipv {} in {:?}", multipv, lines));
+        let pv = line
+            .split_ascii_whitespace()
+            .skip_while(|&token| token != "pv")
+            .nth(1)
+            .unwrap_or_else(|| panic!("no pv in '{}'", line));
+
+        assert_legal(pv, &["j10", "k11"]);
+        assert!(!first_moves.contains(&pv), "{:?}", lines);
+        first_moves.push(pv);
+    }
+}
+
+#[test]
+fn ponders_until_ponderhit() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("position startpos moves j10 k11");
+    engine.send("go ponder wtime 2000 btime 2000");
+    thread::sleep(Duration::from_millis(300));
+    engine.send("isready");
+
+    let lines = engine.expect("readyok", TIMEOUT);
+
+    assert!(
+        lines.iter().all(|line| !line.starts_with("bestmove")),
+        "pondering sent a bestmove before ponderhit: {:?}",
+        lines
+    );
+
+    engine.send("ponderhit");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+
+    assert_legal(&Engine::bestmove(&lines), &["j10", "k11"]);
+}
+
+#[test]
+fn stops_pondering() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("position startpos moves j10 k11");
+    engine.send("go ponder wtime 2000 btime 2000");
+    thread::sleep(Duration::from_millis(300));
+    engine.send("stop");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+
+    assert_legal(&Engine::bestmove(&lines), &["j10", "k11"]);
+}
+
+#[test]
+fn searches_with_several_threads() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("setoption name Threads value 4");
+    engine.send("position startpos moves j10 k11 k10");
+    engine.send("go depth 4");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+
+    assert_legal(&Engine::bestmove(&lines), &["j10", "k11", "k10"]);
+}
+
+#[test]
+fn respects_the_clock() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("position startpos moves j10 k11 k10");
+
+    let start = Instant::now();
+
+    engine.send("go wtime 1000 btime 1000 winc 0 binc 0");
+
+    let lines = engine.expect("bestmove", TIMEOUT);
+
+    assert!(
+        start.elapsed() < Duration::from_millis(1000),
+        "{:?}",
+        start.elapsed()
+    );
+    assert_legal(&Engine::bestmove(&lines), &["j10", "k11", "k10"]);
+}
+
+#[test]
+fn quits() {
+    let mut engine = Engine::spawn();
+
+    engine.handshake();
+    engine.send("position startpos");
+    engine.send("go");
+    engine.send("quit");
+
+    let deadline = Instant::now() + TIMEOUT;
+
+    while engine.child.try_wait().unwrap().is_none() {
+        assert!(Instant::now() < deadline, "the engine did not quit");
+        thread::sleep(Duration::from_millis(10));
+    }
+}